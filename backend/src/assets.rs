@@ -14,19 +14,58 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeSet;
+use std::convert::Infallible;
 use std::fs;
-use std::path::{PathBuf, Path};
-use futures::join;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use actix_web::http::header::{self as http_header, HttpDate};
+use actix_web::rt::time::delay_for;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use futures::stream::{self, StreamExt};
+use image::ImageFormat;
+use reqwest::{header, StatusCode};
+use rustc_hash::FxHashMap;
+use thiserror::Error;
 
 use crate::db::DB;
+use crate::error::NotFound;
+use crate::manifest::Manifest;
+use crate::types::{IconSize, RuneRaw};
+use crate::util;
+use crate::variants::Variants;
 
-const CDN: &str = "https://d2aao99y1mip6n.cloudfront.net";
-const ASSETS: &str = "../frontend/public/assets";
+pub const CDN: &str = "https://d2aao99y1mip6n.cloudfront.net";
+pub const ASSETS: &str = "../frontend/public/assets";
 
-#[derive(Clone, Copy)]
+/// Nothing we mirror comes anywhere close to this, anything bigger is garbage.
+const MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// Longest wait between retries of a single download.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Rune art is addressed by hash, so it never changes under the same name.
+pub const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Ability icons are addressed by name, so they should be revalidated once in a while.
+const CACHE_ICONS: &str = "public, max-age=604800";
+
+/// Directories served under `/assets`, with the extension and caching policy of files in them.
+const SERVED: &[(&str, &str, &str)] = &[
+    ("runes", "jpg", CACHE_IMMUTABLE),
+    ("minis", "png", CACHE_IMMUTABLE),
+    ("idols", "gif", CACHE_IMMUTABLE),
+    ("big_icons", "png", CACHE_ICONS),
+    ("small_icons", "gif", CACHE_ICONS),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum ArtStatus {
     NoChange = 0,
     Success = 1,
+    /// The CDN doesn't have the file
+    Missing = 2,
     Fail = 3,
 }
 
@@ -39,6 +78,7 @@ impl std::ops::BitOr for ArtStatus {
         match bits {
             0 => ArtStatus::NoChange,
             1 => ArtStatus::Success,
+            2 => ArtStatus::Missing,
             _ => ArtStatus::Fail,
         }
     }
@@ -51,116 +91,553 @@ impl ArtStatus {
             ArtStatus::Success => {
                 log::info!("🎨 Downloaded art for [{}] {}", category, name);
             }
-            ArtStatus::Fail => {
+            ArtStatus::Missing | ArtStatus::Fail => {
                 log::warn!("💔 Missing art for [{}] {}", category, name);
             }
         }
     }
 }
 
-async fn download(url: &str, path: &Path) -> ArtStatus {
-    async fn work(url: &str, path: &Path) -> anyhow::Result<()> {
-        use futures_util::StreamExt;
-        use std::io::{BufWriter, Write};
+#[derive(Debug, Error)]
+enum DownloadError {
+    #[error("HTTP status {0}")]
+    Status(StatusCode),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("Unexpected content type {0:?}")]
+    ContentType(String),
+    #[error("Expected {expected} bytes, received {received}")]
+    Truncated { expected: u64, received: usize },
+    #[error("File is too large")]
+    TooLarge,
+    #[error("Not a valid {0:?} image")]
+    Format(ImageFormat),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl DownloadError {
+    /// Whether trying again later might succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            // Invalid URLs and undecodable responses won't fix themselves
+            DownloadError::Request(err) => !(err.is_builder() || err.is_decode()),
+            DownloadError::Truncated { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the CDN doesn't have the file at all. Missing files on the CDN are
+    /// reported as either `404` or `403`.
+    fn is_missing(&self) -> bool {
+        matches!(self, DownloadError::Status(StatusCode::NOT_FOUND | StatusCode::FORBIDDEN))
+    }
+}
+
+/// A single file to be mirrored from the CDN.
+struct Asset {
+    url: String,
+    path: PathBuf,
+}
+
+/// Pool for mirroring assets from the CDN into a local directory, with bounded
+/// concurrency and retries.
+pub struct Downloader {
+    client: reqwest::Client,
+    cdn: Box<str>,
+    dir: PathBuf,
+    concurrency: usize,
+    retries: u32,
+}
+
+impl Downloader {
+    pub fn new(cdn: &str, dir: PathBuf, concurrency: usize, retries: u32) -> Self {
+        Downloader {
+            client: reqwest::Client::new(),
+            cdn: cdn.trim_end_matches('/').into(),
+            dir,
+            concurrency: concurrency.max(1),
+            retries,
+        }
+    }
+
+    /// Asset at `remote` on the CDN, mirrored to `local` in the assets directory.
+    fn asset(&self, remote: String, local: String) -> Asset {
+        Asset {
+            url: format!("{}/{}", self.cdn, remote),
+            path: self.dir.join(local),
+        }
+    }
+
+    fn rune(&self, hash: &str) -> Asset {
+        self.asset(format!("images/runes/lg/{}.jpg", hash), format!("runes/{}.jpg", hash))
+    }
+
+    fn mini(&self, hash: &str) -> Asset {
+        self.asset(format!("images/runes/sm/{}.png", hash), format!("minis/{}.png", hash))
+    }
+
+    fn idol(&self, hash: &str) -> Asset {
+        self.asset(format!("images/runes/idols/{}.gif", hash), format!("idols/{}.gif", hash))
+    }
+
+    fn big_icon(&self, name: &str) -> Asset {
+        self.asset(
+            format!("images/ability_icons/large/icon_{}.png", name),
+            format!("big_icons/icon_{}.png", name),
+        )
+    }
+
+    fn small_icon(&self, name: &str) -> Asset {
+        self.asset(
+            format!("images/ability_icons/small/icon_{}.gif", name),
+            format!("small_icons/icon_{}.gif", name),
+        )
+    }
+
+    /// Download all assets, returning their statuses in the same order.
+    async fn fetch_all(&self, assets: &[Asset]) -> Vec<ArtStatus> {
+        stream::iter(assets)
+            .map(|asset| self.fetch(asset))
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    async fn fetch(&self, asset: &Asset) -> ArtStatus {
+        let mut attempt = 0;
 
-        let res = reqwest::get(url).await?;
+        loop {
+            match self.try_fetch(asset).await {
+                Ok(()) => {
+                    log::debug!("⬇️  {}", asset.url);
+                    return ArtStatus::Success;
+                }
+                Err(err) if err.is_transient() && attempt < self.retries => {
+                    let backoff = Duration::from_millis(500u64.saturating_mul(2u64.saturating_pow(attempt))).min(MAX_BACKOFF);
+
+                    attempt += 1;
+                    log::warn!("🔁 {} {}, retrying in {:?}", asset.url, err, backoff);
+                    delay_for(backoff).await;
+                }
+                Err(err) if err.is_missing() => {
+                    log::warn!("❌ {} {}", asset.url, err);
+                    return ArtStatus::Missing;
+                }
+                Err(err) => {
+                    log::error!("❌ {} {}", asset.url, err);
+                    return ArtStatus::Fail;
+                }
+            }
+        }
+    }
+
+    async fn try_fetch(&self, asset: &Asset) -> Result<(), DownloadError> {
+        let mut res = self.client.get(&asset.url).send().await?;
 
         if !res.status().is_success() {
-            return Err(crate::error::NotFound.into());
+            return Err(DownloadError::Status(res.status()));
         }
-        let mut stream = reqwest::get(url).await?.bytes_stream();
-        let mut file = BufWriter::new(fs::File::create(path)?);
 
-        while let Some(item) = stream.next().await {
-            file.write_all(&item?)?;
+        let content_type = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if !content_type.starts_with("image/") {
+            return Err(DownloadError::ContentType(content_type.into()));
         }
 
+        let expected = res.content_length();
+
+        if expected.is_some_and(|len| len > MAX_SIZE as u64) {
+            return Err(DownloadError::TooLarge);
+        }
+
+        // Content-Length can be missing, so the limit is also enforced while streaming
+        let mut bytes = Vec::with_capacity(expected.unwrap_or(0) as usize);
+
+        while let Some(chunk) = res.chunk().await? {
+            if bytes.len() + chunk.len() > MAX_SIZE {
+                return Err(DownloadError::TooLarge);
+            }
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        match expected {
+            Some(expected) if expected != bytes.len() as u64 => {
+                return Err(DownloadError::Truncated { expected, received: bytes.len() });
+            }
+            _ => (),
+        }
+
+        if let Ok(format) = ImageFormat::from_path(&asset.path) {
+            if image::guess_format(&bytes).ok() != Some(format) {
+                return Err(DownloadError::Format(format));
+            }
+        }
+
+        if let Some(dir) = asset.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        util::write_atomic(&asset.path, |file| file.write_all(&bytes))?;
+
         Ok(())
-    };
+    }
+}
+
+/// Art a single rune needs, logged together once all of it has been checked.
+struct RuneArt<'a> {
+    category: &'static str,
+    name: &'a str,
+    status: ArtStatus,
+}
+
+pub async fn check(db: &mut DB, downloader: &Downloader, variants: &Variants) {
+    let dir = &downloader.dir;
+    let previous = Manifest::load(dir);
+    let no_big_icon = previous.as_ref().map(|previous| previous.no_big_icon.clone()).unwrap_or_default();
+
+    check_runes(db, downloader).await;
+    let no_big_icon = check_icons(db, downloader, no_big_icon).await;
+    check_variants(db, variants).await;
+
+    let manifest = previous
+        .and_then(|previous| Manifest::build(dir, db, &previous))
+        .and_then(|mut manifest| {
+            manifest.no_big_icon = no_big_icon;
+            manifest.save(dir)
+        });
+
+    if let Err(err) = manifest {
+        log::error!("❌ Failed updating the asset manifest: {}", err);
+    }
+}
+
+/// All runes in the DB, with their category and whether they have an idol.
+pub fn runes(db: &DB) -> impl Iterator<Item = (&'static str, &RuneRaw, bool)> {
+    let champs = db.champs.iter().map(|c| ("champ", &c.core.raw, true));
+    let spells = db.spells.iter().map(|s| ("spell", &s.core.raw, false));
+    let equips = db.equips.iter().map(|e| ("equip", &e.core.raw, false));
+    let relics = db.relics.iter().map(|r| ("relic", &r.core.raw, true));
+
+    champs.chain(spells).chain(equips).chain(relics)
+}
 
-    match work(url, path).await {
-        Ok(()) => {
-            log::info!("⬇️  {}", url);
-            ArtStatus::Success
+async fn check_runes(db: &DB, downloader: &Downloader) {
+    let mut runes = Vec::new();
+    let mut missing: Vec<(Vec<usize>, Asset)> = Vec::new();
+
+    // Runes can share art, which must only be downloaded once, as concurrent downloads
+    // of the same file would write over each other. Index of each queued path in `missing`.
+    let mut queued: FxHashMap<PathBuf, usize> = FxHashMap::default();
+
+    for (category, raw, has_idol) in self::runes(db) {
+        let hash = &raw.hash;
+        let owner = runes.len();
+
+        runes.push(RuneArt {
+            category,
+            name: &raw.name,
+            status: ArtStatus::NoChange,
+        });
+
+        let idol = if has_idol { Some(downloader.idol(hash)) } else { None };
+
+        for asset in vec![downloader.rune(hash), downloader.mini(hash)].into_iter().chain(idol) {
+            if let Some(&index) = queued.get(&asset.path) {
+                missing[index].0.push(owner);
+            } else if !asset.path.exists() {
+                queued.insert(asset.path.clone(), missing.len());
+                missing.push((vec![owner], asset));
+            }
         }
-        Err(err) => {
-            log::error!("❌ {} {:?}", url, err);
-            ArtStatus::Fail
+    }
+
+    let (owners, assets): (Vec<Vec<usize>>, Vec<Asset>) = missing.into_iter().unzip();
+
+    for (owners, status) in owners.into_iter().zip(downloader.fetch_all(&assets).await) {
+        for owner in owners {
+            let art = &mut runes[owner];
+
+            art.status = art.status | status;
         }
     }
+
+    for art in runes {
+        art.status.log(art.category, art.name);
+    }
 }
 
-pub async fn check(db: &mut DB) {
-    async fn check_rune(hash: &str) -> ArtStatus {
-        let rune = PathBuf::from(format!("{}/runes/{}.jpg", ASSETS, hash));
+/// Generate resized and re-encoded variants of all rune art that is present.
+async fn check_variants(db: &DB, variants: &Variants) {
+    let hashes = runes(db).map(|(_, raw, _)| raw.hash).collect::<Vec<_>>();
+    let variants = variants.clone();
 
-        if !rune.exists() {
-            let url = format!("{}/images/runes/lg/{}.jpg", CDN, hash);
-            download(&url, &rune).await
-        } else {
-            ArtStatus::NoChange
+    let created = web::block(move || -> Result<usize, Infallible> {
+        let mut created = 0;
+
+        for hash in hashes {
+            match variants.generate(&hash) {
+                Ok(count) => created += count,
+                Err(err) => log::warn!("💔 Couldn't generate variants of {}: {}", hash, err),
+            }
         }
+
+        Ok(created)
+    })
+    .await
+    .unwrap_or(0);
+
+    if created > 0 {
+        log::info!("🖼️  Generated {} variants of rune art", created);
     }
+}
 
-    async fn check_mini(hash: &str) -> ArtStatus {
-        let mini = PathBuf::from(format!("{}/minis/{}.png", ASSETS, hash));
+/// Make sure all ability icons are present, falling back to the small GIF when
+/// the big PNG is not available.
+///
+/// Icons in `no_big_icon` are known to only have a small version on the CDN, so the
+/// big one isn't requested again while the small one is present. Returns the updated
+/// set of such icons.
+async fn check_icons(db: &mut DB, downloader: &Downloader, no_big_icon: BTreeSet<Box<str>>) -> BTreeSet<Box<str>> {
+    // Many abilities share the same icon, only try to fetch each one once
+    let mut icons = FxHashMap::default();
 
-        if !mini.exists() {
-            let url = format!("{}/images/runes/sm/{}.png", CDN, hash);
-            download(&url, &mini).await
+    for ability in db.abilities.iter() {
+        icons.insert(ability.core.icon_name.clone(), IconSize::Missing);
+    }
+
+    let mut pending = Vec::new();
+    let mut small_only = BTreeSet::new();
+
+    for (name, size) in icons.iter_mut() {
+        if downloader.big_icon(name).path.exists() {
+            *size = IconSize::Big;
+        } else if no_big_icon.contains(name) && downloader.small_icon(name).path.exists() {
+            *size = IconSize::Small;
+            small_only.insert(name.clone());
         } else {
-            ArtStatus::NoChange
+            pending.push(name.clone());
         }
     }
 
-    async fn check_idol(hash: &str) -> ArtStatus {
-        let idol = PathBuf::from(format!("{}/idols/{}.gif", ASSETS, hash));
+    let assets = pending.iter().map(|name| downloader.big_icon(name)).collect::<Vec<_>>();
+    let statuses = downloader.fetch_all(&assets).await;
+    let mut fallback = Vec::new();
 
-        if !idol.exists() {
-            let url = format!("{}/images/runes/idols/{}.gif", CDN, hash);
-            download(&url, &idol).await
-        } else {
-            ArtStatus::NoChange
+    for (name, status) in pending.into_iter().zip(statuses) {
+        if status == ArtStatus::Missing {
+            small_only.insert(name.clone());
         }
+
+        let size = match status {
+            ArtStatus::Success => IconSize::Big,
+            _ if downloader.small_icon(&name).path.exists() => IconSize::Small,
+            _ => {
+                fallback.push(name);
+                continue;
+            }
+        };
+
+        icons.insert(name, size);
     }
 
-    for champ in db.champs.iter() {
-        let hash = &champ.core.raw.hash;
-        let (r, m, i) = join!(check_rune(hash), check_mini(hash), check_idol(hash));
+    let assets = fallback.iter().map(|name| downloader.small_icon(name)).collect::<Vec<_>>();
+    let statuses = downloader.fetch_all(&assets).await;
+
+    for (name, status) in fallback.into_iter().zip(statuses) {
+        match status {
+            ArtStatus::Success => {
+                icons.insert(name, IconSize::Small);
+            }
+            _ => log::warn!("💔 Missing icon for [ability] {}", name),
+        }
+    }
 
-        (r | m | i).log("champ", &champ.core.raw.name);
+    for ability in db.abilities.iter_mut() {
+        ability.icon_size = icons[&ability.core.icon_name];
     }
 
-    for spell in db.spells.iter() {
-        let hash = &spell.core.raw.hash;
-        let (r, m) = join!(check_rune(hash), check_mini(hash));
+    small_only
+}
+
+/// Directory the server serves mirrored assets from.
+pub struct AssetDir(pub PathBuf);
 
-        (r | m).log("spell", &spell.core.raw.name);
+fn content_type(ext: &str) -> &'static str {
+    match ext {
+        "jpg" => "image/jpeg",
+        "png" => "image/png",
+        _ => "image/gif",
     }
+}
+
+/// Whether `name` is safe to use as a file name, without any path separators or dots.
+pub fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
 
-    for equip in db.equips.iter() {
-        let hash = &equip.core.raw.hash;
-        let (r, m) = join!(check_rune(hash), check_mini(hash));
+/// Resolve `/assets/{category}/{file}` to a path, refusing anything that isn't a
+/// plain file name with the expected extension.
+fn resolve(dir: &Path, category: &str, file: &str) -> Option<(PathBuf, &'static str, &'static str)> {
+    let &(_, ext, cache) = SERVED.iter().find(|(served, ..)| *served == category)?;
+    let stem = file.strip_suffix(ext)?.strip_suffix('.')?;
 
-        (r | m).log("equip", &equip.core.raw.name);
+    if !is_plain_name(stem) {
+        return None;
     }
 
-    for relic in db.relics.iter() {
-        let hash = &relic.core.raw.hash;
-        let (r, m, i) = join!(check_rune(hash), check_mini(hash), check_idol(hash));
+    Some((dir.join(category).join(file), content_type(ext), cache))
+}
 
-        (r | m | i).log("relic", &relic.core.raw.name);
+/// Serve a mirrored asset.
+pub async fn serve(dir: &AssetDir, req: &HttpRequest, category: &str, file: &str) -> Result<HttpResponse, Error> {
+    let (path, content_type, cache) = resolve(&dir.0, category, file).ok_or(NotFound)?;
+
+    serve_file(req, path, content_type, cache).await
+}
+
+/// Serve a file from disk, answering conditional requests with `304 Not Modified`.
+pub async fn serve_file(
+    req: &HttpRequest,
+    path: PathBuf,
+    content_type: &str,
+    cache: &str,
+) -> Result<HttpResponse, Error> {
+    let meta = {
+        let path = path.clone();
+
+        match web::block(move || fs::metadata(path)).await {
+            Ok(meta) if meta.is_file() => meta,
+            _ => return Err(NotFound.into()),
+        }
+    };
+
+    let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+    let secs = modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let etag = format!("\"{:x}-{:x}\"", meta.len(), secs);
+    let last_modified = HttpDate::from(modified);
+
+    let headers = req.headers();
+    let not_modified = match headers.get(http_header::IF_NONE_MATCH) {
+        Some(value) => value
+            .to_str()
+            .map(|value| value.split(',').any(|tag| {
+                let tag = tag.trim();
+
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            }))
+            .unwrap_or(false),
+        None => headers
+            .get(http_header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok()?.parse::<HttpDate>().ok())
+            .and_then(|since| SystemTime::from(since).duration_since(UNIX_EPOCH).ok())
+            // HTTP dates only have whole seconds
+            .map(|since| since.as_secs() >= secs)
+            .unwrap_or(false),
+    };
+
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+
+    res.set_header(http_header::ETAG, etag.as_str())
+        .set_header(http_header::LAST_MODIFIED, last_modified)
+        .set_header(http_header::CACHE_CONTROL, cache);
+
+    if not_modified {
+        return Ok(res.finish());
     }
 
-    for ability in db.abilities.iter() {
-        let icon = &*ability.core.icon_name;
-        let path = PathBuf::from(format!("{}/big_icons/icon_{}.png", ASSETS, ability.core.icon_name));
+    let body = web::block(move || fs::read(path)).await.map_err(|_| NotFound)?;
+
+    Ok(res.content_type(content_type).body(body))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::rt::System;
+    use actix_web::test::TestRequest;
+    use crate::util::test::TempDir;
 
-        if !path.exists() {
-            log::warn!("{} has a missing icon ({})", &ability.core.name, &ability.core.icon_name);
-            // let url = format!("{}/images/ability_icons/small/icon_{}.gif", CDN, icon);
+    #[test]
+    fn download_errors() {
+        let invalid_url = reqwest::Client::new().get("not a url").build().unwrap_err();
 
-            // download(&url, &path).await.log("ability", &ability.core.name);
+        assert!(!DownloadError::Request(invalid_url).is_transient());
+        assert!(DownloadError::Status(StatusCode::SERVICE_UNAVAILABLE).is_transient());
+        assert!(DownloadError::Truncated { expected: 10, received: 5 }.is_transient());
+        assert!(!DownloadError::Status(StatusCode::NOT_FOUND).is_transient());
+        assert!(DownloadError::Status(StatusCode::NOT_FOUND).is_missing());
+        assert!(!DownloadError::Status(StatusCode::BAD_GATEWAY).is_missing());
+    }
+
+    #[test]
+    fn resolve_plain_names() {
+        let dir = Path::new("assets");
+
+        assert_eq!(
+            resolve(dir, "runes", "draconic_guard-2.jpg"),
+            Some((dir.join("runes").join("draconic_guard-2.jpg"), "image/jpeg", CACHE_IMMUTABLE)),
+        );
+
+        for &(category, file) in &[
+            ("runes", "../manifest.jpg"),
+            ("runes", "..jpg"),
+            ("runes", "/etc/passwd.jpg"),
+            ("runes", "nested/guard.jpg"),
+            ("runes", "nested\\guard.jpg"),
+            ("runes", "guard.png"),
+            ("runes", ".jpg"),
+            ("..", "guard.jpg"),
+            ("variants", "guard.jpg"),
+        ] {
+            assert_eq!(resolve(dir, category, file), None, "{}/{}", category, file);
         }
     }
+
+    async fn serve(path: &Path, req: TestRequest) -> HttpResponse {
+        serve_file(&req.to_http_request(), path.to_owned(), "image/jpeg", CACHE_IMMUTABLE).await.unwrap()
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let dir = TempDir::with_files(&["guard.jpg"]);
+        let path = dir.path().join("guard.jpg");
+
+        System::new("test").block_on(async move {
+            let path = &*path;
+            let res = serve(path, TestRequest::default()).await;
+            let etag = res.headers().get(http_header::ETAG).unwrap().clone();
+            let last_modified = res.headers().get(http_header::LAST_MODIFIED).unwrap().clone();
+
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let res = serve(path, TestRequest::default().header(http_header::IF_NONE_MATCH, etag)).await;
+
+            assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+            let res = serve(path, TestRequest::default().header(http_header::IF_NONE_MATCH, "\"other\"")).await;
+
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let res = serve(path, TestRequest::default().header(http_header::IF_MODIFIED_SINCE, last_modified)).await;
+
+            assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+            let res = serve(path, TestRequest::default().header(http_header::IF_MODIFIED_SINCE, HttpDate::from(UNIX_EPOCH))).await;
+
+            assert_eq!(res.status(), StatusCode::OK);
+        });
+    }
 }
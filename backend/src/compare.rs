@@ -0,0 +1,348 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Side by side comparison of champions.
+//!
+//! Stats are aligned with the list of champions, with deltas relative to the first
+//! one. Ability groups, classes and races are listed once each, marking which of
+//! the champions have them, so that clients don't have to diff them on their own.
+
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::Error;
+
+use crate::db::{AutoIndexTable, DB, TableItem};
+use crate::types::{Ability, Champion, Group, Id, Shim};
+
+/// Most champions that can be compared at once.
+pub const MAX_CHAMPS: usize = 4;
+
+/// Ids of champions to compare, deserialized from a comma separated list of
+/// between 2 and `MAX_CHAMPS` distinct ids, such as `1,2,3`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChampIds(pub Vec<Id>);
+
+impl<'de> Deserialize<'de> for ChampIds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let list = String::deserialize(deserializer)?;
+        let ids = list
+            .split(',')
+            .map(|id| id.trim().parse().map_err(|_| D::Error::custom(format!("invalid champion id `{}`", id))))
+            .collect::<Result<Vec<Id>, _>>()?;
+
+        if ids.len() < 2 || ids.len() > MAX_CHAMPS {
+            return Err(D::Error::custom(format!("expected between 2 and {} champions", MAX_CHAMPS)));
+        }
+        if let Some((_, id)) = ids.iter().enumerate().find(|&(i, id)| ids[..i].contains(id)) {
+            return Err(D::Error::custom(format!("champion `{}` is listed more than once", id)));
+        }
+
+        Ok(ChampIds(ids))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CompareQuery {
+    pub champs: ChampIds,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Stat {
+    NoraCost,
+    HitPoints,
+    Damage,
+    Speed,
+    Defense,
+    MinRng,
+    MaxRng,
+}
+
+impl Stat {
+    const ALL: [Stat; 7] = [
+        Stat::NoraCost,
+        Stat::HitPoints,
+        Stat::Damage,
+        Stat::Speed,
+        Stat::Defense,
+        Stat::MinRng,
+        Stat::MaxRng,
+    ];
+
+    fn of(self, champ: &Champion) -> i32 {
+        let core = &champ.core;
+
+        match self {
+            Stat::NoraCost => core.raw.nora_cost as i32,
+            Stat::HitPoints => core.hit_points as i32,
+            Stat::Damage => core.damage as i32,
+            Stat::Speed => core.speed as i32,
+            Stat::Defense => core.defense as i32,
+            Stat::MinRng => core.min_rng as i32,
+            Stat::MaxRng => core.max_rng as i32,
+        }
+    }
+}
+
+/// How many of the compared champions have something.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum Sharing {
+    All,
+    Some,
+    Unique,
+}
+
+impl Sharing {
+    fn of(has: &[bool]) -> Self {
+        match has.iter().filter(|&&has| has).count() {
+            n if n == has.len() => Sharing::All,
+            1 => Sharing::Unique,
+            _ => Sharing::Some,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct StatComparison {
+    pub stat: Stat,
+    pub values: Vec<i32>,
+    /// Difference from the value of the first champion.
+    pub deltas: Vec<i32>,
+}
+
+/// Class or race, with which of the champions have it.
+#[derive(Serialize, Debug)]
+pub struct Presence<'a> {
+    #[serde(flatten)]
+    pub shim: Shim<'a>,
+    pub has: Vec<bool>,
+    pub sharing: Sharing,
+}
+
+/// Rank of an ability group available to a champion.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RankRef {
+    pub ability: Id,
+    /// Rank of the ability within its group, see `AbilityGroup::order_ranks`.
+    pub rank: u8,
+    /// Index of the upgrade set the ability is in, if it isn't a starting ability.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set: Option<u8>,
+    /// Whether the ability is picked from its set by default.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GroupComparison<'a> {
+    #[serde(flatten)]
+    pub shim: Shim<'a>,
+    /// Ranks each of the champions has, empty for champions without the group.
+    pub ranks: Vec<Vec<RankRef>>,
+    pub sharing: Sharing,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comparison<'a> {
+    pub champs: Vec<&'a Champion>,
+    pub abilities: Vec<&'a Ability>,
+    pub stats: Vec<StatComparison>,
+    /// Groups shared by all champions first, then by some, then unique ones.
+    pub ability_groups: Vec<GroupComparison<'a>>,
+    pub classes: Vec<Presence<'a>>,
+    pub races: Vec<Presence<'a>>,
+}
+
+/// Classes or races of all champions, in order of first appearance.
+fn presence<'a>(
+    champs: &[&Champion],
+    ids: impl Fn(&Champion) -> &[Id],
+    groups: &'a AutoIndexTable<Group<Champion>>,
+) -> Option<Vec<Presence<'a>>> {
+    let mut list = Vec::<Presence>::new();
+
+    for (i, champ) in champs.iter().enumerate() {
+        for &id in ids(champ) {
+            let entry = match list.iter_mut().position(|entry| entry.shim.id == id) {
+                Some(index) => &mut list[index],
+                None => {
+                    list.push(Presence {
+                        shim: groups.get(id)?.shim(),
+                        has: vec![false; champs.len()],
+                        sharing: Sharing::All,
+                    });
+                    list.last_mut().unwrap()
+                }
+            };
+
+            entry.has[i] = true;
+        }
+    }
+
+    for entry in &mut list {
+        entry.sharing = Sharing::of(&entry.has);
+    }
+
+    list.sort_by_key(|entry| entry.sharing);
+
+    Some(list)
+}
+
+impl<'a> Comparison<'a> {
+    /// Compare champions, returning `None` if any of them, or any of their
+    /// abilities, classes or races, doesn't exist.
+    pub fn new(db: &'a DB, ids: &ChampIds) -> Option<Self> {
+        let champs = ids.0.iter().map(|&id| db.champs.get(id)).collect::<Option<Vec<_>>>()?;
+
+        let stats = Stat::ALL
+            .iter()
+            .map(|&stat| {
+                let values = champs.iter().map(|champ| stat.of(champ)).collect::<Vec<_>>();
+                let deltas = values.iter().map(|value| value - values[0]).collect();
+
+                StatComparison { stat, values, deltas }
+            })
+            .collect();
+
+        let mut abilities = Vec::<&Ability>::new();
+        let mut ability_groups = Vec::<GroupComparison>::new();
+
+        for (i, champ) in champs.iter().enumerate() {
+            let starting = champ.starting_abilities.iter().map(|&id| (id, None));
+            let sets = champ
+                .ability_sets
+                .iter()
+                .enumerate()
+                .flat_map(|(set, ids)| ids.iter().map(move |&id| (id, Some(set as u8))));
+
+            for (id, set) in starting.chain(sets) {
+                let ability = db.abilities.get(id)?;
+                let group = db.ability_groups.get(ability.group)?;
+
+                let entry = match ability_groups.iter_mut().position(|entry| entry.shim.id == group.id()) {
+                    Some(index) => &mut ability_groups[index],
+                    None => {
+                        ability_groups.push(GroupComparison {
+                            shim: group.shim(),
+                            ranks: champs.iter().map(|_| Vec::new()).collect(),
+                            sharing: Sharing::All,
+                        });
+                        ability_groups.last_mut().unwrap()
+                    }
+                };
+
+                entry.ranks[i].push(RankRef {
+                    ability: id,
+                    rank: ability.rank,
+                    set,
+                    default: set.is_some() && champ.defaults.contains(&id),
+                });
+
+                if !abilities.iter().any(|ability| ability.id() == id) {
+                    abilities.push(ability);
+                }
+            }
+        }
+
+        for entry in &mut ability_groups {
+            let has = entry.ranks.iter().map(|ranks| !ranks.is_empty()).collect::<Vec<_>>();
+
+            entry.sharing = Sharing::of(&has);
+
+            for ranks in &mut entry.ranks {
+                ranks.sort_by_key(|rank| rank.rank);
+            }
+        }
+
+        ability_groups.sort_by_key(|entry| entry.sharing);
+
+        Some(Comparison {
+            classes: presence(&champs, |champ| &champ.classes, &db.classes)?,
+            races: presence(&champs, |champ| &champ.races, &db.races)?,
+            champs,
+            abilities,
+            stats,
+            ability_groups,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn champ_ids() {
+        let parse = |list: &str| serde_json::from_value::<ChampIds>(list.into());
+
+        assert_eq!(parse("1, 2,3").unwrap(), ChampIds(vec![1, 2, 3]));
+        assert!(parse("1").is_err());
+        assert!(parse("1,2,3,4,5").is_err());
+        assert!(parse("1,two").is_err());
+        assert!(parse("1,2,1").is_err());
+
+        assert_eq!(Sharing::of(&[true, true, true]), Sharing::All);
+        assert_eq!(Sharing::of(&[true, false, true]), Sharing::Some);
+        assert_eq!(Sharing::of(&[false, true]), Sharing::Unique);
+    }
+
+    #[test]
+    fn comparison() {
+        let db = crate::parser::test::fixture();
+        let comparison = Comparison::new(&db, &ChampIds(vec![1, 2, 4])).unwrap();
+
+        let stat = |stat| comparison.stats.iter().find(|comparison| comparison.stat == stat).unwrap();
+
+        assert_eq!(stat(Stat::NoraCost).values, [60, 52, 40]);
+        assert_eq!(stat(Stat::NoraCost).deltas, [0, -8, -20]);
+        assert_eq!(stat(Stat::HitPoints).deltas, [0, -6, 4]);
+
+        let groups = comparison
+            .ability_groups
+            .iter()
+            .map(|group| (group.shim.name, group.sharing))
+            .collect::<Vec<_>>();
+
+        assert_eq!(groups, [
+            ("Attack Boost", Sharing::All),
+            ("Armor", Sharing::All),
+            ("Flanking", Sharing::Some),
+            ("Fire Breath", Sharing::Some),
+            ("Regenerate", Sharing::Some),
+        ]);
+
+        let ranks = |group: usize, champ: usize| {
+            comparison.ability_groups[group].ranks[champ]
+                .iter()
+                .map(|rank| (rank.ability, rank.rank, rank.set, rank.default))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranks(0, 0), [(201, 1, Some(0), true), (211, 2, Some(0), false)]);
+        assert_eq!(ranks(0, 1), [(211, 2, Some(0), true)]);
+        assert_eq!(ranks(2, 0), [(101, 1, None, false)]);
+        assert!(ranks(2, 2).is_empty());
+
+        let classes = comparison.classes.iter().map(|class| (class.shim.name, class.sharing)).collect::<Vec<_>>();
+
+        assert_eq!(classes, [("Warrior", Sharing::Some), ("Spirit", Sharing::Unique)]);
+        assert_eq!(comparison.classes[0].has, [true, false, true]);
+
+        assert!(Comparison::new(&db, &ChampIds(vec![1, 99])).is_none());
+    }
+}
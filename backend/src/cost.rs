@@ -0,0 +1,197 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Nora cost of a champion with a selection of upgrade abilities.
+//!
+//! The nora cost in the feed is that of the champion with its default upgrades,
+//! so the cost of any other selection is the base cost, without the defaults,
+//! plus the cost of each selected upgrade. This is the same model the champion
+//! page of the frontend has always used to show the cost of the picked upgrades,
+//! see `frontend/src/pages/ChampionPage.tsx`.
+
+use arrayvec::ArrayVec;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::Error;
+
+use crate::db::DB;
+use crate::types::{Ability, Champion, Id};
+
+/// Ids of selected upgrade abilities, deserialized from a comma separated list
+/// of at most one ability per upgrade set, such as `201,311`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UpgradeIds(pub ArrayVec<[Id; 2]>);
+
+impl<'de> Deserialize<'de> for UpgradeIds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let list = String::deserialize(deserializer)?;
+        let mut ids = ArrayVec::new();
+
+        for id in list.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+            let id = id.parse().map_err(|_| D::Error::custom(format!("invalid ability id `{}`", id)))?;
+
+            ids.try_push(id).map_err(|_| D::Error::custom("expected at most 2 upgrades"))?;
+        }
+
+        Ok(UpgradeIds(ids))
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CostQuery {
+    pub upgrades: UpgradeIds,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpgradeCost {
+    pub ability: Id,
+    pub set: u8,
+    pub nora_cost: i32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+}
+
+/// Action points used by the activated abilities of a champion.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ApProfile {
+    /// Number of activated abilities.
+    pub activated: u32,
+    /// AP needed to use every activated ability once.
+    pub total: u32,
+    pub min: Option<u8>,
+    pub max: Option<u8>,
+}
+
+impl ApProfile {
+    fn of<'a>(abilities: impl IntoIterator<Item = &'a Ability>) -> Self {
+        let mut profile = ApProfile::default();
+
+        for ability in abilities.into_iter().filter(|ability| ability.core.is_activated()) {
+            let ap = ability.core.ap_cost;
+
+            profile.activated += 1;
+            profile.total += ap as u32;
+            profile.min = Some(profile.min.map_or(ap, |min| min.min(ap)));
+            profile.max = Some(profile.max.map_or(ap, |max| max.max(ap)));
+        }
+
+        profile
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CostBreakdown<'a> {
+    pub champ: Id,
+    /// Cost of the champion without any upgrades.
+    pub base: i32,
+    pub upgrades: Vec<UpgradeCost>,
+    pub total: i32,
+    pub ap: ApProfile,
+    /// Starting abilities followed by the selected upgrades.
+    pub abilities: Vec<&'a Ability>,
+}
+
+impl<'a> CostBreakdown<'a> {
+    /// Cost of `champ` with upgrades picked by `Champion::upgrades`, returning
+    /// `None` if any of its abilities don't exist.
+    pub fn new(db: &'a DB, champ: &Champion, picks: [Option<Id>; 2]) -> Option<Self> {
+        let defaults = champ
+            .defaults
+            .iter()
+            .zip(&champ.ability_sets)
+            .filter(|(id, set)| set.contains(id))
+            .map(|(&id, _)| Some(db.abilities.get(id)?.core.nora_cost as i32))
+            .sum::<Option<i32>>()?;
+
+        let base = champ.core.raw.nora_cost as i32 - defaults;
+        let mut upgrades = Vec::new();
+        let mut abilities = champ
+            .starting_abilities
+            .iter()
+            .map(|&id| db.abilities.get(id))
+            .collect::<Option<Vec<_>>>()?;
+
+        for (set, pick) in picks.iter().enumerate() {
+            if let Some(id) = *pick {
+                let ability = db.abilities.get(id)?;
+
+                upgrades.push(UpgradeCost {
+                    ability: id,
+                    set: set as u8,
+                    nora_cost: ability.core.nora_cost as i32,
+                    default: champ.defaults[set] == id,
+                });
+                abilities.push(ability);
+            }
+        }
+
+        Some(CostBreakdown {
+            champ: champ.core.raw.id,
+            base,
+            total: base + upgrades.iter().map(|upgrade| upgrade.nora_cost).sum::<i32>(),
+            upgrades,
+            ap: ApProfile::of(abilities.iter().copied()),
+            abilities,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgrade_ids() {
+        let parse = |list: &str| serde_json::from_value::<UpgradeIds>(list.into());
+
+        assert_eq!(parse("201, 311").unwrap().0.as_slice(), &[201, 311]);
+        assert_eq!(parse("").unwrap(), UpgradeIds::default());
+        assert!(parse("201,211,301").is_err());
+        assert!(parse("attack").is_err());
+    }
+
+    #[test]
+    fn cost_breakdown() {
+        let db = crate::parser::test::fixture();
+        let cost = |champ: Id, picks: [Option<Id>; 2]| {
+            let champ = db.champs.get(champ).unwrap();
+
+            CostBreakdown::new(&db, champ, champ.picks(picks).unwrap()).unwrap()
+        };
+
+        // Defaults are Attack Boost (5) and an activated Fire Breath (8, 3 AP)
+        let defaults = cost(1, [None, None]);
+
+        assert_eq!((defaults.base, defaults.total), (47, 60));
+        assert_eq!(defaults.upgrades.iter().filter(|upgrade| upgrade.default).count(), 2);
+        assert_eq!(defaults.ap, ApProfile { activated: 1, total: 3, min: Some(3), max: Some(3) });
+        assert_eq!(defaults.abilities.iter().map(|ability| ability.core.id).collect::<Vec<_>>(), [101, 201, 301]);
+
+        // Attack Boost (2) for 10 and Armor for 4, both passive
+        let passive = cost(1, [Some(211), Some(311)]);
+
+        assert_eq!((passive.base, passive.total), (47, 61));
+        assert_eq!(passive.ap, ApProfile::default());
+
+        // Default Fire Breath (8, 3 AP) with Volley (7, 5 AP) instead of Regenerate (6)
+        let golem = cost(5, [None, Some(402)]);
+
+        assert_eq!((golem.base, golem.total), (56, 71));
+        assert_eq!(golem.ap, ApProfile { activated: 2, total: 8, min: Some(3), max: Some(5) });
+    }
+}
@@ -19,6 +19,7 @@ use std::ops::{Index, IndexMut};
 use std::hash::Hash;
 use std::borrow::Borrow;
 use rustc_hash::FxHashMap;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
 
 use crate::types::Id;
 use crate::db::TableItem;
@@ -80,6 +81,34 @@ impl<T: AutoIndexItem> AutoIndexTable<T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.table.iter()
     }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.table.iter_mut()
+    }
+}
+
+impl<T> Serialize for AutoIndexTable<T>
+where
+    T: AutoIndexItem + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.table.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for AutoIndexTable<T>
+where
+    T: AutoIndexItem + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = Vec::<T>::deserialize(deserializer)?;
+        let ids = table
+            .iter()
+            .map(|item| (T::make_key(item.key()), item.id()))
+            .collect();
+
+        Ok(AutoIndexTable { ids, table })
+    }
 }
 
 impl<T: AutoIndexItem> Index<Id> for AutoIndexTable<T> {
@@ -101,5 +130,7 @@ pub trait AutoIndexItem: TableItem {
 
     fn create(id: Id, key: Self::Key) -> Self;
 
+    fn key(&self) -> &str;
+
     fn make_key(key: &str) -> Self::Key;
 }
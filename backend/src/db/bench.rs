@@ -0,0 +1,156 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Benchmarks for building and querying the `SearchIndex` on a generated corpus
+//! about the size of the live feed, and ten times that. Run with:
+//!
+//! ```text
+//! cargo test --release bench -- --ignored --nocapture --test-threads=1
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::db::{Score, SearchIndex};
+use crate::types::EntityId;
+
+const SYLLABLES: &[&str] = &[
+    "ka", "ra", "tor", "vel", "min", "dra", "gon", "el", "ith", "ur", "sha", "mon", "bel", "dor",
+    "fa", "lin", "quo", "zen", "pha", "rix", "ta", "os", "nim", "bra",
+];
+
+/// Deterministic xorshift, so every run works on the same corpus.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+struct Corpus {
+    vocabulary: Vec<String>,
+    names: Vec<String>,
+    texts: Vec<String>,
+}
+
+impl Corpus {
+    fn new(scale: usize) -> Self {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let vocabulary = (0..4000 * scale)
+            .map(|_| (0..2 + rng.next(3)).map(|_| SYLLABLES[rng.next(SYLLABLES.len())]).collect())
+            .collect::<Vec<String>>();
+        let sentence = |rng: &mut Rng, min: usize, max: usize| {
+            let words = min + rng.next(max - min);
+
+            (0..words).map(|_| &*vocabulary[rng.next(vocabulary.len())]).collect::<Vec<_>>().join(" ")
+        };
+
+        let names = (0..3000 * scale).map(|_| sentence(&mut rng, 1, 4)).collect();
+        let texts = (0..3000 * scale).map(|_| sentence(&mut rng, 15, 45)).collect();
+
+        Corpus { vocabulary, names, texts }
+    }
+
+    fn index(texts: &[String]) -> SearchIndex {
+        let mut index = SearchIndex::builder();
+
+        for (id, text) in texts.iter().enumerate() {
+            index.insert(text, EntityId::Champion(id as u32));
+        }
+
+        index.build()
+    }
+
+    /// Queries made of one to three words, either cut short or with two letters swapped.
+    fn queries(&self, typo: bool) -> Vec<String> {
+        let mut rng = Rng(42);
+
+        (0..200)
+            .map(|_| {
+                let words = (0..1 + rng.next(3)).map(|_| {
+                    let mut word = self.vocabulary[rng.next(self.vocabulary.len())].clone().into_bytes();
+
+                    if typo {
+                        let i = rng.next(word.len() - 1);
+                        word.swap(i, i + 1);
+                    } else {
+                        word.truncate(3 + rng.next(word.len() - 2));
+                    }
+
+                    String::from_utf8(word).unwrap()
+                });
+
+                words.collect::<Vec<_>>().join(" ")
+            })
+            .collect()
+    }
+}
+
+fn report(name: &str, runs: usize, elapsed: Duration) {
+    println!("{:<32} {:>12.1?} per run ({} runs)", name, elapsed / runs as u32, runs);
+}
+
+fn bench_build(name: &str, texts: &[String]) {
+    let runs = 5;
+    let start = Instant::now();
+    let mut size = 0;
+
+    for _ in 0..runs {
+        size = Corpus::index(texts).size();
+    }
+
+    report(name, runs, start.elapsed());
+    println!("{:<32} {:>10}kb", "", size / 1024);
+}
+
+fn bench_find(name: &str, index: &SearchIndex, queries: &[String], find: fn(&SearchIndex, &str) -> Vec<(EntityId, Score)>) {
+    let start = Instant::now();
+    let mut found = 0;
+
+    for query in queries {
+        found += find(index, query).len();
+    }
+
+    report(name, queries.len(), start.elapsed());
+    assert!(found > 0);
+}
+
+fn bench_scale(scale: usize) {
+    let corpus = Corpus::new(scale);
+    let names = Corpus::index(&corpus.names);
+    let texts = Corpus::index(&corpus.texts);
+    let prefix = corpus.queries(false);
+    let typos = corpus.queries(true);
+
+    println!("\n{} names, {} texts", corpus.names.len(), corpus.texts.len());
+
+    bench_build("build names", &corpus.names);
+    bench_build("build texts", &corpus.texts);
+    bench_find("find names, prefix", &names, &prefix, SearchIndex::find);
+    bench_find("find names, typos", &names, &typos, SearchIndex::find);
+    bench_find("find texts, prefix", &texts, &prefix, SearchIndex::find_text);
+    bench_find("find texts, typos", &texts, &typos, SearchIndex::find_text);
+}
+
+#[test]
+#[ignore]
+fn bench_search() {
+    bench_scale(1);
+    bench_scale(10);
+}
@@ -14,10 +14,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use serde::{Serialize, Deserialize};
+
 use crate::db::{Id, TableItem};
 use crate::types::RuneSet;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct EnumTable<E: EnumId> {
     table: Vec<RuneSet<E>>
 }
@@ -54,4 +56,8 @@ where
     pub fn entry(&mut self, item: E) -> &mut RuneSet<E> {
         &mut self.table[item.id() as usize]
     }
+
+    pub fn get(&self, id: Id) -> Option<&RuneSet<E>> {
+        self.table.get(id as usize)
+    }
 }
@@ -14,19 +14,31 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use serde::{Serialize, Deserialize};
+
 use crate::types::{Id, Ability, AbilityGroup, Champion, Effect, Rune, Spell, Equip, Relic, Group, Faction, RuneSet};
+use crate::types::{EntityId, EntityShim, Tags};
 
 mod table;
 mod autoindex;
 mod enum_table;
 mod search;
+mod text;
+mod query;
+mod rank;
+pub mod snapshot;
+#[cfg(test)]
+mod bench;
 
 pub use table::{Table, TableItem};
 pub use autoindex::{AutoIndexTable, AutoIndexItem};
 pub use enum_table::{EnumTable, EnumId};
-pub use search::{SearchIndex, SearchId, Searchable};
+pub use search::{Score, SearchIndex, SearchId, SearchKinds, Searchable};
+pub use text::{snippet, Fragment};
+pub use query::{RuneQuery, RuneShim, TypeaheadQuery};
+pub use rank::{Popularity, Ranking};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct DB {
     pub ability_groups: AutoIndexTable<AbilityGroup>,
     pub abilities: Table<Ability>,
@@ -41,4 +53,51 @@ pub struct DB {
     pub factions: EnumTable<Faction>,
     pub expansions: AutoIndexTable<RuneSet>,
     pub search: SearchIndex,
+    /// Index of descriptions and flavor text
+    pub text: SearchIndex,
+}
+
+impl DB {
+    /// Resolve an entity to its name, kind and rarity.
+    pub fn shim(&self, eid: EntityId) -> Option<EntityShim<'_>> {
+        let (sid, name, rarity) = match eid {
+            EntityId::Champion(id) => {
+                let rune = self.champs.get(id)?;
+
+                (SearchId::Champion(id), &rune.core.raw.name, Some(rune.core.raw.rarity))
+            }
+            EntityId::Spell(id) => {
+                let rune = self.spells.get(id)?;
+
+                (SearchId::Spell(id), &rune.core.raw.name, Some(rune.core.raw.rarity))
+            }
+            EntityId::Equip(id) => {
+                let rune = self.equips.get(id)?;
+
+                (SearchId::Equip(id), &rune.core.raw.name, Some(rune.core.raw.rarity))
+            }
+            EntityId::Relic(id) => {
+                let rune = self.relics.get(id)?;
+
+                (SearchId::Relic(id), &rune.core.raw.name, Some(rune.core.raw.rarity))
+            }
+            EntityId::AbilityGroup(id) => {
+                let group = self.ability_groups.get(id)?;
+
+                (SearchId::AbilityGroup(id), &group.name, None)
+            }
+            EntityId::Effect(id) => {
+                let effect = self.effects.get(id)?;
+
+                (effect.search_id(), &effect.name, None)
+            }
+        };
+
+        Some(EntityShim { name, sid, rarity })
+    }
+
+    /// Resolve all tagged entities, skipping any that are missing.
+    pub fn shims<'a>(&'a self, tags: &'a Tags) -> impl Iterator<Item = EntityShim<'a>> + 'a {
+        tags.iter().filter_map(move |eid| self.shim(eid))
+    }
 }
@@ -0,0 +1,419 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{DB, SearchId, SearchKinds, TableItem};
+use crate::types::{Id, Champion, EntityId, EntityShim, Faction, Rarity, RuneKind, RuneRaw, Size};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+
+const DEFAULT_TYPEAHEAD_LIMIT: usize = 10;
+const MAX_TYPEAHEAD_LIMIT: usize = 50;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortBy {
+    Id,
+    #[default]
+    Name,
+    Rarity,
+    NoraCost,
+    HitPoints,
+    Speed,
+    Damage,
+    MaxRng,
+    Defense,
+}
+
+/// Filters for the `/runes` endpoint, deserialized from query parameters.
+///
+/// Stat filters only apply to champions, so setting any of them excludes spells,
+/// equipment and relics from the results.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RuneQuery {
+    pub kind: Option<RuneKind>,
+    pub faction: Option<Id>,
+    pub rarity: Option<u8>,
+    pub min_nora: Option<u16>,
+    pub max_nora: Option<u16>,
+    pub min_hit_points: Option<u16>,
+    pub max_hit_points: Option<u16>,
+    pub min_speed: Option<u8>,
+    pub max_speed: Option<u8>,
+    pub min_damage: Option<u16>,
+    pub max_damage: Option<u16>,
+    pub min_rng: Option<u8>,
+    pub max_rng: Option<u8>,
+    pub min_defense: Option<u8>,
+    pub max_defense: Option<u8>,
+    pub size: Option<Size>,
+    pub class: Option<Id>,
+    pub race: Option<Id>,
+    pub expansion: Option<Id>,
+    pub allow_ranked: Option<bool>,
+    pub for_sale: Option<bool>,
+    pub sort: SortBy,
+    pub desc: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Filters for the `/typeahead` endpoint, deserialized from query parameters.
+///
+/// Only runes have a faction and rarity, so setting either excludes ability groups
+/// and effects from the results.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct TypeaheadQuery {
+    pub kind: Option<SearchKinds>,
+    pub faction: Option<Id>,
+    pub rarity: Option<u8>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TypeaheadResult<'a> {
+    pub total: usize,
+    pub results: Vec<EntityShim<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RuneShim<'a> {
+    pub name: &'a str,
+    #[serde(flatten)]
+    pub sid: SearchId<'a>,
+    pub rarity: Rarity,
+    pub nora_cost: u16,
+    pub factions: &'a [Faction],
+    pub hash: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct QueryResult<'a> {
+    pub total: usize,
+    pub runes: Vec<RuneShim<'a>>,
+}
+
+/// Common view over all four rune tables.
+pub struct RuneView<'a> {
+    pub sid: SearchId<'a>,
+    pub raw: &'a RuneRaw,
+    pub factions: &'a [Faction],
+    pub expansion: Id,
+    pub champ: Option<&'a Champion>,
+}
+
+impl<'a> RuneView<'a> {
+    pub fn shim(&self) -> RuneShim<'a> {
+        RuneShim {
+            name: &self.raw.name,
+            sid: self.sid,
+            rarity: self.raw.rarity,
+            nora_cost: self.raw.nora_cost,
+            factions: self.factions,
+            hash: &self.raw.hash,
+        }
+    }
+}
+
+fn in_range<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+impl RuneQuery {
+    fn has_stat_filters(&self) -> bool {
+        self.min_hit_points.is_some()
+            || self.max_hit_points.is_some()
+            || self.min_speed.is_some()
+            || self.max_speed.is_some()
+            || self.min_damage.is_some()
+            || self.max_damage.is_some()
+            || self.min_rng.is_some()
+            || self.max_rng.is_some()
+            || self.min_defense.is_some()
+            || self.max_defense.is_some()
+            || self.size.is_some()
+            || self.class.is_some()
+            || self.race.is_some()
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+
+    fn wants(&self, kind: RuneKind) -> bool {
+        match self.kind {
+            Some(k) => k == kind,
+            None => kind == RuneKind::Champion || !self.has_stat_filters(),
+        }
+    }
+
+    fn matches(&self, view: &RuneView) -> bool {
+        let raw = view.raw;
+
+        if let Some(faction) = self.faction {
+            if !view.factions.iter().any(|f| f.id() == faction) {
+                return false;
+            }
+        }
+        if self.rarity.is_some_and(|rarity| rarity != raw.rarity as u8)
+            || self.expansion.is_some_and(|expansion| expansion != view.expansion)
+            || self.allow_ranked.is_some_and(|ranked| ranked != raw.allow_ranked)
+            || self.for_sale.is_some_and(|for_sale| for_sale != raw.for_sale)
+            || !in_range(raw.nora_cost, self.min_nora, self.max_nora)
+        {
+            return false;
+        }
+
+        let champ = match view.champ {
+            Some(champ) => champ,
+            None => return !self.has_stat_filters(),
+        };
+        let core = &champ.core;
+
+        in_range(core.hit_points, self.min_hit_points, self.max_hit_points)
+            && in_range(core.speed, self.min_speed, self.max_speed)
+            && in_range(core.damage, self.min_damage, self.max_damage)
+            && in_range(core.max_rng, self.min_rng, self.max_rng)
+            && in_range(core.defense, self.min_defense, self.max_defense)
+            && self.size.is_none_or(|size| size == core.size)
+            && self.class.is_none_or(|class| champ.classes.contains(&class))
+            && self.race.is_none_or(|race| champ.races.contains(&race))
+    }
+
+    fn compare(&self, a: &RuneView, b: &RuneView) -> Ordering {
+        fn stat<T: Ord + Copy>(view: &RuneView, f: impl Fn(&Champion) -> T) -> Option<T> {
+            view.champ.map(f)
+        }
+
+        let ord = match self.sort {
+            SortBy::Id => a.raw.id.cmp(&b.raw.id),
+            SortBy::Name => Ordering::Equal,
+            SortBy::Rarity => (a.raw.rarity as u8).cmp(&(b.raw.rarity as u8)),
+            SortBy::NoraCost => a.raw.nora_cost.cmp(&b.raw.nora_cost),
+            SortBy::HitPoints => stat(a, |c| c.core.hit_points).cmp(&stat(b, |c| c.core.hit_points)),
+            SortBy::Speed => stat(a, |c| c.core.speed).cmp(&stat(b, |c| c.core.speed)),
+            SortBy::Damage => stat(a, |c| c.core.damage).cmp(&stat(b, |c| c.core.damage)),
+            SortBy::MaxRng => stat(a, |c| c.core.max_rng).cmp(&stat(b, |c| c.core.max_rng)),
+            SortBy::Defense => stat(a, |c| c.core.defense).cmp(&stat(b, |c| c.core.defense)),
+        };
+
+        // Always fall back to name, then id, so pagination is stable
+        let ord = ord
+            .then_with(|| a.raw.name.cmp(&b.raw.name))
+            .then_with(|| a.sid.cmp(&b.sid));
+
+        if self.desc {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+impl TypeaheadQuery {
+    fn has_rune_filters(&self) -> bool {
+        self.faction.is_some() || self.rarity.is_some()
+    }
+
+    fn matches(&self, db: &DB, eid: EntityId) -> bool {
+        let sid = match db.shim(eid) {
+            Some(shim) => shim.sid,
+            None => return false,
+        };
+
+        if self.kind.is_some_and(|kinds| !kinds.contains(sid.kind())) {
+            return false;
+        }
+        if !self.has_rune_filters() {
+            return true;
+        }
+
+        db.rune_of(eid).is_some_and(|view| {
+            self.faction.is_none_or(|faction| view.factions.iter().any(|f| f.id() == faction))
+                && self.rarity.is_none_or(|rarity| rarity == view.raw.rarity as u8)
+        })
+    }
+}
+
+impl DB {
+    pub fn rune(&self, kind: RuneKind, id: Id) -> Option<RuneView<'_>> {
+        match kind {
+            RuneKind::Champion => self.champs.get(id).map(|rune| RuneView {
+                sid: SearchId::Champion(id),
+                raw: &rune.core.raw,
+                factions: &rune.factions,
+                expansion: rune.expansion,
+                champ: Some(rune),
+            }),
+            RuneKind::Spell => self.spells.get(id).map(|rune| RuneView {
+                sid: SearchId::Spell(id),
+                raw: &rune.core.raw,
+                factions: &rune.factions,
+                expansion: rune.expansion,
+                champ: None,
+            }),
+            RuneKind::Equip => self.equips.get(id).map(|rune| RuneView {
+                sid: SearchId::Equip(id),
+                raw: &rune.core.raw,
+                factions: &rune.factions,
+                expansion: rune.expansion,
+                champ: None,
+            }),
+            RuneKind::Relic => self.relics.get(id).map(|rune| RuneView {
+                sid: SearchId::Relic(id),
+                raw: &rune.core.raw,
+                factions: &rune.factions,
+                expansion: rune.expansion,
+                champ: None,
+            }),
+        }
+    }
+
+    /// View of the rune behind `eid`, if it is one.
+    pub fn rune_of(&self, eid: EntityId) -> Option<RuneView<'_>> {
+        match eid {
+            EntityId::Champion(id) => self.rune(RuneKind::Champion, id),
+            EntityId::Spell(id) => self.rune(RuneKind::Spell, id),
+            EntityId::Equip(id) => self.rune(RuneKind::Equip, id),
+            EntityId::Relic(id) => self.rune(RuneKind::Relic, id),
+            EntityId::AbilityGroup(_) | EntityId::Effect(_) => None,
+        }
+    }
+
+    pub fn query(&self, query: &RuneQuery) -> QueryResult<'_> {
+        let mut views = Vec::new();
+
+        if query.wants(RuneKind::Champion) {
+            views.extend(self.champs.iter().filter_map(|rune| self.rune(RuneKind::Champion, rune.id())));
+        }
+        if query.wants(RuneKind::Spell) {
+            views.extend(self.spells.iter().filter_map(|rune| self.rune(RuneKind::Spell, rune.id())));
+        }
+        if query.wants(RuneKind::Equip) {
+            views.extend(self.equips.iter().filter_map(|rune| self.rune(RuneKind::Equip, rune.id())));
+        }
+        if query.wants(RuneKind::Relic) {
+            views.extend(self.relics.iter().filter_map(|rune| self.rune(RuneKind::Relic, rune.id())));
+        }
+
+        views.retain(|view| query.matches(view));
+        views.sort_unstable_by(|a, b| query.compare(a, b));
+
+        QueryResult {
+            total: views.len(),
+            runes: views
+                .iter()
+                .skip(query.offset)
+                .take(query.limit())
+                .map(RuneView::shim)
+                .collect(),
+        }
+    }
+
+    /// Find entities by name, ranking only those that pass the filters.
+    pub fn typeahead(&self, text: &str, query: &TypeaheadQuery) -> TypeaheadResult<'_> {
+        let matches = self.search.find_where(text, |eid| query.matches(self, eid));
+        let limit = query.limit.unwrap_or(DEFAULT_TYPEAHEAD_LIMIT).min(MAX_TYPEAHEAD_LIMIT);
+
+        TypeaheadResult {
+            total: matches.len(),
+            results: matches
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .filter_map(|(eid, _)| self.shim(eid))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rune_query() {
+        let db = crate::parser::test::fixture();
+        let names = |query: RuneQuery| db.query(&query).runes.iter().map(|rune| rune.name).collect::<Vec<_>>();
+
+        assert_eq!(names(RuneQuery::default()), [
+            "Draconic Guard", "Fireball", "Flame Wraith", "Ironfist Grunt", "K'thir Hunter", "Stone Golem", "Sword", "Tower",
+        ]);
+
+        // Stat filters exclude spells, equipment and relics
+        assert_eq!(
+            names(RuneQuery { min_speed: Some(4), ..Default::default() }),
+            ["Draconic Guard", "Flame Wraith", "K'thir Hunter"],
+        );
+        assert!(names(RuneQuery { kind: Some(RuneKind::Spell), min_speed: Some(0), ..Default::default() }).is_empty());
+
+        // Bounds are inclusive
+        assert_eq!(
+            names(RuneQuery { min_nora: Some(48), max_nora: Some(52), sort: SortBy::NoraCost, ..Default::default() }),
+            ["K'thir Hunter", "Fireball", "Sword", "Tower", "Flame Wraith"],
+        );
+        assert_eq!(
+            names(RuneQuery { min_hit_points: Some(41), max_hit_points: Some(45), ..Default::default() }),
+            ["Draconic Guard", "Ironfist Grunt"],
+        );
+    }
+
+    #[test]
+    fn rune_query_sort() {
+        let db = crate::parser::test::fixture();
+        let names = |query: RuneQuery| db.query(&query).runes.iter().map(|rune| rune.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(RuneQuery { kind: Some(RuneKind::Champion), sort: SortBy::HitPoints, desc: true, ..Default::default() }),
+            ["Stone Golem", "Ironfist Grunt", "Draconic Guard", "Flame Wraith", "K'thir Hunter"],
+        );
+
+        // Runes without the stat come first, ordered by name
+        assert_eq!(
+            names(RuneQuery { sort: SortBy::Speed, ..Default::default() }),
+            ["Fireball", "Sword", "Tower", "Stone Golem", "Ironfist Grunt", "Draconic Guard", "Flame Wraith", "K'thir Hunter"],
+        );
+        assert_eq!(
+            names(RuneQuery { sort: SortBy::Id, desc: true, ..Default::default() }),
+            ["Tower", "Sword", "Fireball", "Stone Golem", "Ironfist Grunt", "K'thir Hunter", "Flame Wraith", "Draconic Guard"],
+        );
+    }
+
+    #[test]
+    fn rune_query_pages() {
+        let db = crate::parser::test::fixture();
+
+        let page = db.query(&RuneQuery { offset: 6, limit: Some(5), ..Default::default() });
+
+        assert_eq!(page.total, 8);
+        assert_eq!(page.runes.iter().map(|rune| rune.name).collect::<Vec<_>>(), ["Sword", "Tower"]);
+
+        let past_end = db.query(&RuneQuery { offset: 100, ..Default::default() });
+
+        assert_eq!(past_end.total, 8);
+        assert!(past_end.runes.is_empty());
+
+        assert_eq!(RuneQuery::default().limit(), DEFAULT_LIMIT);
+        assert_eq!(RuneQuery { limit: Some(10_000), ..Default::default() }.limit(), MAX_LIMIT);
+    }
+}
@@ -0,0 +1,273 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Ranking signals that don't depend on the query.
+//!
+//! Every entity gets a small boost for its kind, for runes that are legal in ranked
+//! play or for sale, and for how popular it is with players, if a popularity file
+//! is provided. Boosts never lift a match above one with fewer typos, they only
+//! reorder matches of similar quality, such as all names starting with `dragon`.
+
+use std::fs;
+use std::path::Path;
+use anyhow::Context;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use crate::db::search::{SearchKind, Searchable};
+use crate::db::DB;
+use crate::types::{EntityId, Id};
+
+/// Bonus for every kind of entity.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct KindWeights {
+    pub champion: i32,
+    pub spell: i32,
+    pub equip: i32,
+    pub relic: i32,
+    pub ability: i32,
+    pub effect: i32,
+    pub condition: i32,
+    pub mechanic: i32,
+    pub damage: i32,
+}
+
+impl Default for KindWeights {
+    fn default() -> Self {
+        KindWeights {
+            champion: 4,
+            spell: 3,
+            equip: 3,
+            relic: 3,
+            ability: 2,
+            effect: 0,
+            condition: 2,
+            mechanic: 1,
+            damage: 1,
+        }
+    }
+}
+
+impl KindWeights {
+    fn get(&self, kind: SearchKind) -> i32 {
+        match kind {
+            SearchKind::Champion => self.champion,
+            SearchKind::Spell => self.spell,
+            SearchKind::Equip => self.equip,
+            SearchKind::Relic => self.relic,
+            SearchKind::AbilityGroup => self.ability,
+            SearchKind::Effect => self.effect,
+            SearchKind::Condition => self.condition,
+            SearchKind::Mechanic => self.mechanic,
+            SearchKind::Damage => self.damage,
+        }
+    }
+}
+
+/// Weights of the ranking signals, loaded from a JSON file such as:
+///
+/// ```json
+/// { "kinds": { "champion": 5, "ability": 0 }, "allowRanked": 2, "forSale": 0, "popularity": 8 }
+/// ```
+///
+/// Anything left out keeps its default. For comparison, every letter of the query
+/// matched is worth 8. The sum of all signals is clamped between 0 and 15, see
+/// `search::MAX_BOOST`, which the defaults never exceed.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Ranking {
+    pub kinds: KindWeights,
+    pub allow_ranked: i32,
+    pub for_sale: i32,
+    /// Bonus for the most popular entity, others get a share proportional to their weight.
+    pub popularity: i32,
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Ranking {
+            kinds: KindWeights::default(),
+            allow_ranked: 2,
+            for_sale: 1,
+            popularity: 8,
+        }
+    }
+}
+
+impl Ranking {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+
+        serde_json::from_slice(&json).with_context(|| format!("Invalid ranking in {}", path.display()))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum PopularityKey {
+    Id(Id),
+    Key(Box<str>),
+}
+
+#[derive(Deserialize, Debug)]
+struct PopularityEntry {
+    kind: SearchKind,
+    id: PopularityKey,
+    weight: f64,
+}
+
+/// How popular entities are with players, loaded from a JSON file listing them
+/// the same way the API does, with a weight in any unit, such as play counts:
+///
+/// ```json
+/// [{ "kind": "champion", "id": 1234, "weight": 5120 }, { "kind": "condition", "id": "frozen", "weight": 80 }]
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct Popularity {
+    entries: Vec<PopularityEntry>,
+}
+
+impl Popularity {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+
+        serde_json::from_slice(&json).with_context(|| format!("Invalid popularity in {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Popularity of every entity in the `db`, relative to the most popular one.
+    fn resolve(&self, db: &DB) -> FxHashMap<EntityId, f64> {
+        let max = self.entries.iter().map(|entry| entry.weight).fold(0.0, f64::max);
+
+        if max <= 0.0 {
+            return FxHashMap::default();
+        }
+
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let eid = match (entry.kind, &entry.id) {
+                    (SearchKind::Champion, PopularityKey::Id(id)) => EntityId::Champion(*id),
+                    (SearchKind::Spell, PopularityKey::Id(id)) => EntityId::Spell(*id),
+                    (SearchKind::Equip, PopularityKey::Id(id)) => EntityId::Equip(*id),
+                    (SearchKind::Relic, PopularityKey::Id(id)) => EntityId::Relic(*id),
+                    (SearchKind::AbilityGroup, PopularityKey::Id(id)) => EntityId::AbilityGroup(*id),
+                    (kind, PopularityKey::Key(key)) => {
+                        let effect = db.effects.get_by_key(key)?;
+
+                        // Keys only name effects, and only of the kind they are listed as
+                        if effect.search_id().kind() != kind {
+                            return None;
+                        }
+
+                        EntityId::Effect(effect.id)
+                    }
+                    _ => return None,
+                };
+
+                Some((eid, entry.weight.max(0.0) / max))
+            })
+            .collect()
+    }
+}
+
+impl DB {
+    /// Compute the boost of every entity and store it in the name index.
+    pub fn rank(&mut self, ranking: &Ranking, popularity: &Popularity) {
+        let popularity = popularity.resolve(self);
+        let mut boosts = FxHashMap::default();
+
+        for eid in self.documents() {
+            let sid = match self.shim(eid) {
+                Some(shim) => shim.sid,
+                None => continue,
+            };
+
+            let mut boost = ranking.kinds.get(sid.kind());
+
+            if let Some(view) = self.rune_of(eid) {
+                if view.raw.allow_ranked {
+                    boost += ranking.allow_ranked;
+                }
+                if view.raw.for_sale {
+                    boost += ranking.for_sale;
+                }
+            }
+            if let Some(share) = popularity.get(&eid) {
+                boost += (share * ranking.popularity as f64).round() as i32;
+            }
+
+            boosts.insert(eid, boost);
+        }
+
+        self.search.set_boosts(boosts);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::search::MAX_BOOST;
+
+    #[test]
+    fn partial_ranking() {
+        let ranking: Ranking = serde_json::from_str(r#"{ "kinds": { "ability": 0 }, "forSale": 0 }"#).unwrap();
+
+        assert_eq!(ranking.kinds.ability, 0);
+        assert_eq!(ranking.kinds.champion, KindWeights::default().champion);
+        assert_eq!(ranking.for_sale, 0);
+        assert_eq!(ranking.allow_ranked, Ranking::default().allow_ranked);
+    }
+
+    #[test]
+    fn default_boosts_fit() {
+        let ranking = Ranking::default();
+        let kinds = &ranking.kinds;
+        let runes = [kinds.champion, kinds.spell, kinds.equip, kinds.relic];
+        let others = [kinds.ability, kinds.effect, kinds.condition, kinds.mechanic, kinds.damage];
+
+        // Popularity would be flattened by the cap, if any kind could exceed it
+        for kind in runes.iter() {
+            assert!(kind + ranking.allow_ranked + ranking.for_sale + ranking.popularity <= MAX_BOOST);
+        }
+        for kind in others.iter() {
+            assert!(kind + ranking.popularity <= MAX_BOOST);
+        }
+    }
+
+    #[test]
+    fn popularity_keys() {
+        let db = crate::parser::test::fixture();
+        let popularity: Popularity = serde_json::from_str(r#"[
+            { "kind": "condition", "id": "burning", "weight": 10 },
+            { "kind": "champion", "id": "flanking", "weight": 10 },
+            { "kind": "mechanic", "id": "burning", "weight": 10 },
+            { "kind": "champion", "id": 2, "weight": 5 }
+        ]"#).unwrap();
+
+        let burning = db.effects.get_by_key("burning").unwrap().id;
+        let mut resolved = popularity.resolve(&db).into_iter().collect::<Vec<_>>();
+
+        resolved.sort_by_key(|&(eid, _)| eid);
+
+        assert_eq!(resolved, [(EntityId::Champion(2), 0.5), (EntityId::Effect(burning), 1.0)]);
+    }
+}
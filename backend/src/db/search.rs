@@ -15,14 +15,43 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::cmp::Ordering;
-use arrayvec::ArrayString;
-use serde::Serialize;
+use std::ops::Range;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::{Error, IntoDeserializer};
 use rustc_hash::FxHashMap;
-use std::collections::hash_map::Entry::*;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 use crate::types::{Id, EntityId};
 
-type Word = ArrayString<[u8; 15]>;
+pub type Word = Box<str>;
+
+/// Longest query word, in bytes, that is still matched with typos.
+const MAX_FUZZY: usize = 32;
+
+/// Largest boost of an entity, worth less than two letters of the query, so that
+/// ranking signals only reorder matches of similar quality.
+pub const MAX_BOOST: i32 = 15;
+
+/// How well an entity matches a query. Matches with fewer typos always rank higher,
+/// `points` only order matches with the same number of typos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Score {
+    pub typos: u32,
+    pub points: i32,
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.typos.cmp(&self.typos).then(self.points.cmp(&other.points))
+    }
+}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
 #[derive(Serialize, Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(tag = "kind", content = "id", rename_all = "camelCase")]
@@ -35,139 +64,600 @@ pub enum SearchId<'a> {
     AbilityGroup(Id),
     Effect(&'a str),
     Condition(&'a str),
+    Mechanic(&'a str),
     Damage(&'a str),
 }
 
+/// Kind of a `SearchId`, named the same way it is serialized.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchKind {
+    Champion,
+    Spell,
+    Equip,
+    Relic,
+    #[serde(rename = "ability")]
+    AbilityGroup,
+    Effect,
+    Condition,
+    Mechanic,
+    Damage,
+}
+
+impl SearchId<'_> {
+    pub fn kind(&self) -> SearchKind {
+        match self {
+            SearchId::Champion(_) => SearchKind::Champion,
+            SearchId::Spell(_) => SearchKind::Spell,
+            SearchId::Equip(_) => SearchKind::Equip,
+            SearchId::Relic(_) => SearchKind::Relic,
+            SearchId::AbilityGroup(_) => SearchKind::AbilityGroup,
+            SearchId::Effect(_) => SearchKind::Effect,
+            SearchId::Condition(_) => SearchKind::Condition,
+            SearchId::Mechanic(_) => SearchKind::Mechanic,
+            SearchId::Damage(_) => SearchKind::Damage,
+        }
+    }
+}
+
+/// Set of `SearchKind`s, deserialized from a comma separated list such as
+/// `champion,spell`. `rune` stands for all four kinds of runes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchKinds(u16);
+
+impl SearchKinds {
+    pub const RUNES: SearchKinds = SearchKinds(0b1111);
+
+    pub fn contains(self, kind: SearchKind) -> bool {
+        self.0 & 1 << kind as u16 != 0
+    }
+
+    pub fn insert(&mut self, kind: SearchKind) {
+        self.0 |= 1 << kind as u16;
+    }
+}
+
+impl<'de> Deserialize<'de> for SearchKinds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let list = String::deserialize(deserializer)?;
+        let mut kinds = SearchKinds::default();
+
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            if name == "rune" {
+                kinds.0 |= SearchKinds::RUNES.0;
+                continue;
+            }
+
+            kinds.insert(SearchKind::deserialize(name.into_deserializer())?);
+        }
+
+        if kinds == SearchKinds::default() {
+            return Err(D::Error::custom("expected at least one kind"));
+        }
+
+        Ok(kinds)
+    }
+}
+
 pub trait Searchable {
-    fn search_id(&self) -> SearchId;
+    fn search_id(&self) -> SearchId<'_>;
 }
 
-#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct Entry {
     eid: EntityId,
     ord: u32,
 }
 
-#[derive(Debug)]
+/// Index of words, stored as a sorted arena of distinct words, each with its own
+/// list of entries.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SearchIndex {
+    /// All distinct words in sorted order, concatenated.
+    words: String,
+    /// Offsets of each word in `words` and of its entries in `entries`, with an extra
+    /// slot at the end, so that word `i` ends where word `i + 1` starts.
+    slots: Vec<Slot>,
+    entries: Vec<Entry>,
+    /// Bonus added to the score of an entity whenever it matches, see `DB::rank`.
+    /// Depends on configuration rather than the feed, so it isn't saved in snapshots.
+    #[serde(skip)]
+    boosts: FxHashMap<EntityId, i32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Slot {
+    word: u32,
+    entries: u32,
+}
+
+/// Collects words of all texts to build a `SearchIndex` in one go.
+#[derive(Default)]
+pub struct SearchIndexBuilder {
     table: Vec<(Word, Entry)>,
 }
 
-impl Default for SearchIndex {
-    fn default() -> Self {
-        SearchIndex {
-            table: Vec::new(),
+impl SearchIndexBuilder {
+    pub fn insert(&mut self, text: &str, eid: EntityId) {
+        for (order, word) in SearchIndex::split_words(text).enumerate() {
+            self.table.push((word, Entry { eid, ord: order as u32 }));
+        }
+    }
+
+    pub fn build(mut self) -> SearchIndex {
+        self.table.sort_unstable();
+
+        let mut index = SearchIndex {
+            entries: Vec::with_capacity(self.table.len()),
+            ..SearchIndex::default()
+        };
+        let mut last = 0;
+
+        for (word, entry) in self.table {
+            if index.slots.is_empty() || index.words[last..] != *word {
+                last = index.words.len();
+                index.slots.push(Slot {
+                    word: last as u32,
+                    entries: index.entries.len() as u32,
+                });
+                index.words.push_str(&word);
+            }
+
+            index.entries.push(entry);
+        }
+
+        index.slots.push(Slot {
+            word: index.words.len() as u32,
+            entries: index.entries.len() as u32,
+        });
+        index.words.shrink_to_fit();
+        index.slots.shrink_to_fit();
+        index
+    }
+}
+
+/// First index in `0..len` for which `pred` is false, `pred` being true for all
+/// indexes before it and false for all after.
+fn partition_point(len: usize, mut pred: impl FnMut(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, len);
+
+    while low < high {
+        let mid = low + (high - low) / 2;
+
+        if pred(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
         }
     }
+
+    low
 }
 
-fn left_bound<T: Ord>(a: &T, b: &T) -> Ordering {
-    if a < b {
-        Ordering::Less
-    } else {
-        Ordering::Greater
+/// Maximum number of typos tolerated in a query word of a given length.
+fn max_typos(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=6 => 1,
+        7..=MAX_FUZZY => 2,
+        _ => 0,
     }
 }
 
-fn right_bound(a: &str, b: &str) -> Ordering {
-    if a.starts_with(b) {
-        Ordering::Less
-    } else {
-        a.cmp(b)
+/// First bytes of the words worth checking for a fuzzy match of the query `word`.
+///
+/// Only the first `max + 1` bytes of the query can line up with the start of a word
+/// within `max` typos, unless the typo is in the very first letter. Those are rare,
+/// and not looking for them keeps fuzzy matching to a few buckets of the index,
+/// rather than every word in it. Swapping the first two letters still matches.
+fn fuzzy_firsts(word: &str, max: usize) -> &[u8] {
+    &word.as_bytes()[..word.len().min(max + 1)]
+}
+
+/// Set of letters in a word, as a bitmask.
+fn letters(word: &[u8]) -> u32 {
+    word.iter().fold(0, |mask, &b| mask | 1 << (b.wrapping_sub(b'a') & 31))
+}
+
+/// Optimal string alignment distance between `query` and the closest prefix of `word`,
+/// so typos and swapped letters are tolerated while still allowing incomplete words.
+/// Returns `None` as soon as the distance is known to exceed `max`.
+///
+/// Distance is measured in bytes, so a typo in a letter outside of ASCII that
+/// survived normalization counts as more than one.
+fn prefix_distance(query: &[u8], word: &[u8], max: usize) -> Option<usize> {
+    // Can't possibly get within `max` if the word is too short
+    if word.len() + max < query.len() || query.len() > MAX_FUZZY {
+        return None;
+    }
+
+    // Nor if there are more letters in the query missing from the word than typos allowed
+    if (letters(query) & !letters(word)).count_ones() as usize > max {
+        return None;
     }
+
+    // Only cells within `max` of the diagonal are computed, so with the query length
+    // limited three rows of the matrix fit on the stack
+    const N: usize = MAX_FUZZY + 4;
+
+    let mut prev2 = [0u8; N];
+    let mut prev = [0u8; N];
+    let mut row = [0u8; N];
+
+    for (j, cell) in prev.iter_mut().enumerate() {
+        *cell = j as u8;
+    }
+
+    for i in 1..=query.len() {
+        // Cells further than `max` off the diagonal can never be within `max`
+        let cols = word.len().min(i + max);
+
+        row[0] = i as u8;
+
+        let mut row_min = row[0];
+
+        for j in 1..=cols {
+            let cost = (query[i - 1] != word[j - 1]) as u8;
+            let mut dist = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+
+            if i > 1 && j > 1 && query[i - 1] == word[j - 2] && query[i - 2] == word[j - 1] {
+                dist = dist.min(prev2[j - 2] + 1);
+            }
+
+            row[j] = dist;
+            row_min = row_min.min(dist);
+        }
+
+        if cols < word.len() {
+            row[cols + 1] = u8::MAX / 2;
+        }
+
+        if row_min as usize > max {
+            return None;
+        }
+
+        prev2 = prev;
+        prev = row;
+    }
+
+    let cols = word.len().min(query.len() + max);
+
+    prev[..=cols].iter().map(|&dist| dist as usize).min().filter(|&dist| dist <= max)
+}
+
+/// Apostrophes are dropped from words rather than splitting them, and combining
+/// marks are dropped after decomposition.
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || is_combining_mark(ch) || matches!(ch, '\'' | '’' | 'ʼ')
 }
 
 impl SearchIndex {
-    pub fn insert(&mut self, text: &str, eid: EntityId) {
-        for (order, word) in Self::split_words(text).enumerate() {
-            let entry = Entry {
-                eid,
-                ord: order as u32,
-            };
+    pub fn builder() -> SearchIndexBuilder {
+        SearchIndexBuilder::default()
+    }
 
-            let index = self.table.binary_search_by(|(w, _)| w.cmp(&word)).unwrap_or_else(|i| i);
+    /// Set the boost of every entity, clamped between 0 and `MAX_BOOST`.
+    pub fn set_boosts(&mut self, boosts: FxHashMap<EntityId, i32>) {
+        self.boosts = boosts
+            .into_iter()
+            .map(|(eid, boost)| (eid, boost.clamp(0, MAX_BOOST)))
+            .filter(|&(_, boost)| boost != 0)
+            .collect();
+    }
 
-            self.table.insert(index, (word, entry));
-        }
+    /// Number of distinct words.
+    fn len(&self) -> usize {
+        self.slots.len().saturating_sub(1)
     }
 
-    pub fn find(&self, query: &str) -> Vec<(EntityId, i32)> {
-        let mut entries = FxHashMap::default();
-        let mut sums = FxHashMap::default();
-        let mut threshold = 0;
+    fn word(&self, i: usize) -> &str {
+        &self.words[self.slots[i].word as usize..self.slots[i + 1].word as usize]
+    }
+
+    fn entries(&self, i: usize) -> &[Entry] {
+        &self.entries[self.slots[i].entries as usize..self.slots[i + 1].entries as usize]
+    }
 
-        for (order, word) in Self::split_words(query).take(10).enumerate() {
+    /// Find entities matching all words of the `query`, best matches first.
+    ///
+    /// Words are matched as prefixes, falling back to matching with a few typos for
+    /// longer words. Matches are ranked by their number of typos first, so exact and
+    /// prefix matches always rank above fuzzy ones, whatever their boosts.
+    pub fn find(&self, query: &str) -> Vec<(EntityId, Score)> {
+        self.search(query, true, |_| true)
+    }
+
+    /// Like `find`, but only for entities accepted by the `filter`, so that they
+    /// are ranked among themselves.
+    pub fn find_where(&self, query: &str, filter: impl Fn(EntityId) -> bool) -> Vec<(EntityId, Score)> {
+        self.search(query, true, filter)
+    }
+
+    /// Like `find`, but for indexes of longer text, where the position of words
+    /// within the text doesn't affect the score.
+    pub fn find_text(&self, query: &str) -> Vec<(EntityId, Score)> {
+        self.search(query, false, |_| true)
+    }
+
+    /// Whether a word from the text matches a word of the query, the same way `find` would.
+    pub fn matches(query: &Word, word: &Word) -> bool {
+        let max = max_typos(query.len());
+
+        word.starts_with(&**query)
+            || (word.as_bytes().first().is_some_and(|first| fuzzy_firsts(query, max).contains(first))
+                && prefix_distance(query.as_bytes(), word.as_bytes(), max).is_some())
+    }
+
+    /// Range of words starting with the byte `first`.
+    fn bucket(&self, first: u8) -> Range<usize> {
+        let first = Some(&first);
+        let start = partition_point(self.len(), |i| self.word(i).as_bytes().first() < first);
+        let end = start + partition_point(self.len() - start, |i| self.word(start + i).as_bytes().first() == first);
+
+        start..end
+    }
+
+    fn search(&self, query: &str, positional: bool, filter: impl Fn(EntityId) -> bool) -> Vec<(EntityId, Score)> {
+        let query = Self::split_words(query).take(10).collect::<Vec<_>>();
+
+        // Every entry matching any word of the query, with the order of that word
+        let mut hits = Vec::new();
+
+        for (order, word) in query.iter().enumerate() {
             let order = order as u32;
-            let score = (word.len() * 8) as i32;
-
-            // Ignoring order of things, we must at least find all the letters
-            threshold += score;
-
-            let start = self.table.binary_search_by(|(w, _)| left_bound(w, &word)).unwrap_or_else(|i| i);
-            let count = self.table[start..].binary_search_by(|(w, _)| right_bound(w, &word)).unwrap_or_else(|i| i);
-            let matches = &self.table[start..start + count];
-
-            for (w, entry) in matches {
-                // Add a bonus to the score if the word order matches query
-                let mut score = score + 7;
-                score -= std::cmp::min((w.len() - word.len()) as i32, 4);
-                score -= ((order as i32) - (entry.ord as i32)).abs();
-
-                match entries.entry(Entry {
-                    eid: entry.eid,
-                    ord: order,
-                }) {
-                    Vacant(vacant) => {
-                        vacant.insert(score);
-                        *sums.entry(entry.eid).or_insert(0) += score;
-                    }
-                    Occupied(occupied) => {
-                        let s = *occupied.get();
 
-                        if score > s {
-                            *sums.entry(entry.eid).or_insert(0) += score - s;
-                        }
+            let mut add = |i: usize, typos: usize| {
+                let w = self.word(i);
+                let mut points = (word.len() * 8) as i32 + 7;
+                points -= std::cmp::min(w.len().saturating_sub(word.len()) as i32, 4);
+
+                for entry in self.entries(i).iter().filter(|entry| filter(entry.eid)) {
+                    // Add a bonus to the score if the word order matches query
+                    let bonus = if positional {
+                        -((order as i32) - (entry.ord as i32)).abs()
+                    } else {
+                        0
+                    };
+
+                    hits.push((entry.eid, order, Score { typos: typos as u32, points: points + bonus }));
+                }
+            };
+
+            let start = partition_point(self.len(), |i| self.word(i) < &**word);
+            let end = start + partition_point(self.len() - start, |i| self.word(start + i).starts_with(&**word));
+
+            for i in start..end {
+                add(i, 0);
+            }
+
+            let max = max_typos(word.len());
+
+            if max == 0 {
+                continue;
+            }
+
+            let firsts = fuzzy_firsts(word, max);
+
+            for (k, &first) in firsts.iter().enumerate() {
+                if firsts[..k].contains(&first) {
+                    continue;
+                }
+
+                // Prefix matches have been found already
+                for i in self.bucket(first).filter(|i| !(start..end).contains(i)) {
+                    if let Some(typos) = prefix_distance(word.as_bytes(), self.word(i).as_bytes(), max) {
+                        add(i, typos);
                     }
                 }
             }
         }
 
-        let mut sorted = sums.into_iter().filter(|(_, score)| *score >= threshold).collect::<Vec<_>>();
+        // Group hits by entity, and within that by query word, best score first
+        hits.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then(b.2.cmp(&a.2)));
+
+        let mut sorted = hits
+            .chunk_by(|a, b| a.0 == b.0)
+            .filter_map(|group| {
+                let mut score = Score::default();
+                let mut matched = 0;
+                let mut last = None;
+
+                for &(_, order, s) in group {
+                    if last != Some(order) {
+                        last = Some(order);
+                        score.typos += s.typos;
+                        score.points += s.points;
+                        matched += 1;
+                    }
+                }
+
+                let eid = group[0].0;
 
-        sorted.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+                score.points += self.boosts.get(&eid).copied().unwrap_or(0);
+
+                if matched == query.len() {
+                    Some((eid, score))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        sorted.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
         sorted
     }
 
+    /// Split text into normalized words of letters and digits.
+    ///
+    /// Words are case folded and stripped of diacritics, so `Éclair` is the same as
+    /// `eclair`. Apostrophes are dropped without splitting the word, so `K'thir` is
+    /// indexed as `kthir`, while any other punctuation separates words.
     pub fn split_words(text: &str) -> impl Iterator<Item = Word> + '_ {
-        text.split_ascii_whitespace().map(|w| {
-            let mut word = Word::new();
+        text.split(|ch: char| !is_word_char(ch)).filter_map(|raw| {
+            let mut word = String::with_capacity(raw.len());
 
-            let bytes = w.bytes().filter_map(|b| match b | 0x20 {
-                ch @ b'a'..=b'z' => Some(ch),
-                _ => None
-            });
-
-            for byte in bytes.take(15) {
-                word.push(byte as char);
+            // Most text is plain ASCII, which needs no normalization
+            if raw.is_ascii() {
+                word.extend(raw.bytes().filter(u8::is_ascii_alphanumeric).map(|b| b.to_ascii_lowercase() as char));
+            } else {
+                Self::normalize(raw, &mut word);
             }
 
-            word
+            if word.is_empty() {
+                None
+            } else {
+                Some(word.into())
+            }
         })
     }
 
+    fn normalize(raw: &str, word: &mut String) {
+        for ch in raw.nfkd().filter(|&ch| ch.is_alphanumeric()) {
+            match ch {
+                'ß' => word.push_str("ss"),
+                ch => word.extend(ch.to_lowercase()),
+            }
+        }
+    }
+
     pub fn size(&self) -> usize {
-        self.table.len() * std::mem::size_of::<(Word, Entry)>()
+        self.words.len()
+            + self.slots.len() * std::mem::size_of::<Slot>()
+            + self.entries.len() * std::mem::size_of::<Entry>()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::db::Ranking;
 
     #[test]
     fn split_words() {
         let split = SearchIndex::split_words("Hello W'orld!").collect::<Vec<_>>();
 
-        assert_eq!(&split, &[Word::from("hello").unwrap(), Word::from("world").unwrap()]);
+        assert_eq!(&split, &[Word::from("hello"), Word::from("world")]);
+    }
+
+    #[test]
+    fn split_words_unicode() {
+        let split = |text| SearchIndex::split_words(text).collect::<Vec<_>>();
+
+        // Case folding and diacritics, both precomposed and combining
+        assert_eq!(split("Éclair ÜBER Straße"), ["eclair", "uber", "strasse"].map(Word::from));
+        assert_eq!(split("Cafe\u{301} ﬁre"), ["cafe", "fire"].map(Word::from));
+
+        // Apostrophes don't split words, other punctuation does
+        assert_eq!(split("K'thir K’thir Half-Orc"), ["kthir", "kthir", "half", "orc"].map(Word::from));
+
+        // Digits are kept
+        assert_eq!(split("Level 3 Hydra2000"), ["level", "3", "hydra2000"].map(Word::from));
+
+        // Long words are kept whole
+        assert_eq!(split("Uncharacteristically"), [Word::from("uncharacteristically")]);
+    }
+
+    #[test]
+    fn long_words_dont_collide() {
+        let mut index = SearchIndex::builder();
+
+        index.insert("Incomprehensibilities", EntityId::Champion(1));
+        index.insert("Incomprehensibility", EntityId::Champion(2));
+
+        let index = index.build();
+
+        let find = |query| index.find(query).into_iter().map(|(eid, _)| eid).collect::<Vec<_>>();
+
+        assert_eq!(find("incomprehensibilities"), [EntityId::Champion(1)]);
+        assert_eq!(find("incomprehensibility"), [EntityId::Champion(2), EntityId::Champion(1)]);
+        assert_eq!(find("k'thir"), []);
+    }
+
+    #[test]
+    fn search_kinds() {
+        let parse = |list: &str| {
+            SearchKinds::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(list))
+        };
+
+        let kinds = parse("rune, condition").unwrap();
+
+        assert!(kinds.contains(SearchKind::Champion));
+        assert!(kinds.contains(SearchKind::Relic));
+        assert!(kinds.contains(SearchKind::Condition));
+        assert!(!kinds.contains(SearchKind::AbilityGroup));
+        assert!(!kinds.contains(SearchKind::Effect));
+        assert!(parse("ability").unwrap().contains(SearchKind::AbilityGroup));
+        assert!(parse("champ").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn boosts() {
+        let mut index = SearchIndex::builder();
+
+        index.insert("Dragonkin", EntityId::Champion(1));
+        index.insert("Dragon", EntityId::AbilityGroup(2));
+        index.insert("Dargon", EntityId::Relic(3));
+
+        let mut index = index.build();
+        let find = |index: &SearchIndex| index.find("dragon").into_iter().map(|(eid, _)| eid).collect::<Vec<_>>();
+
+        assert_eq!(find(&index), [EntityId::AbilityGroup(2), EntityId::Champion(1), EntityId::Relic(3)]);
+
+        // Even the largest boost doesn't lift a typo above a longer prefix match
+        index.set_boosts(vec![(EntityId::Relic(3), MAX_BOOST)].into_iter().collect());
+
+        assert_eq!(find(&index), [EntityId::AbilityGroup(2), EntityId::Champion(1), EntityId::Relic(3)]);
+
+        // Boosts reorder similar matches
+        let ranking = Ranking::default();
+
+        index.set_boosts(vec![(EntityId::Champion(1), ranking.kinds.champion), (EntityId::Relic(3), 100)].into_iter().collect());
+
+        assert_eq!(find(&index), [EntityId::Champion(1), EntityId::AbilityGroup(2), EntityId::Relic(3)]);
+    }
+
+    #[test]
+    fn fuzzy_find() {
+        let mut index = SearchIndex::builder();
+
+        index.insert("Draconic Guard", EntityId::Champion(1));
+        index.insert("Flame Wraith", EntityId::Champion(2));
+        index.insert("Flanking", EntityId::AbilityGroup(3));
+        index.insert("Gurd", EntityId::Relic(4));
+
+        let index = index.build();
+
+        let find = |query| index.find(query).into_iter().map(|(eid, _)| eid).collect::<Vec<_>>();
+
+        assert_eq!(find("flankng"), [EntityId::AbilityGroup(3)]);
+        assert_eq!(find("draconic gaurd"), [EntityId::Champion(1)]);
+        assert_eq!(find("drac"), [EntityId::Champion(1)]);
+        assert_eq!(find("fla").len(), 2);
+        assert_eq!(find("guard"), [EntityId::Champion(1), EntityId::Relic(4)]);
+        assert!(find("xyz").is_empty());
+
+        // Swapped first letters are found, a typo in the first letter is not
+        assert_eq!(find("rdaconic"), [EntityId::Champion(1)]);
+        assert!(find("traconic").is_empty());
+
+        let relics = index.find_where("guard", |eid| matches!(eid, EntityId::Relic(_)));
+
+        assert_eq!(relics.iter().map(|&(eid, _)| eid).collect::<Vec<_>>(), [EntityId::Relic(4)]);
+    }
+
+    #[test]
+    fn every_query_word_matches() {
+        let mut index = SearchIndex::builder();
+
+        index.insert("Guard of the Guard", EntityId::Champion(1));
+        index.insert("Flame Guard", EntityId::Champion(2));
+
+        let index = index.build();
+        let find = |query| index.find(query).into_iter().map(|(eid, _)| eid).collect::<Vec<_>>();
+
+        // A word repeated in the name still only matches one word of the query
+        assert_eq!(find("guard flame"), [EntityId::Champion(2)]);
+        assert_eq!(find("guard guard").len(), 2);
     }
 }
@@ -0,0 +1,149 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Binary snapshots of a fully parsed `DB`, so the server can start without
+//! waiting for the feed.
+//!
+//! The file starts with a fixed header: 4 bytes of magic, a little-endian `u32`
+//! format version and the 32 byte Keccak hash of the feed. The rest is the `DB`
+//! encoded as CBOR.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use anyhow::{bail, Context};
+use serde::{Serialize, Deserialize};
+
+use crate::db::DB;
+use crate::types::{Id, Tags};
+use crate::util;
+
+const MAGIC: &[u8; 4] = b"PXBS";
+
+/// Bump this whenever the layout of anything stored in the `DB` changes.
+const VERSION: u32 = 6;
+
+/// Tags of effects and ability groups are skipped when serializing them for the API,
+/// so they have to be stored separately.
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    db: &'a DB,
+    effect_tags: Vec<&'a Tags>,
+    group_tags: Vec<&'a Tags>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    db: DB,
+    effect_tags: Vec<Tags>,
+    group_tags: Vec<Tags>,
+}
+
+pub fn save(db: &DB, hash: &[u8; 32], path: &Path) -> anyhow::Result<()> {
+    let snapshot = SnapshotRef {
+        db,
+        effect_tags: db.effects.iter().map(|effect| &effect.tags).collect(),
+        group_tags: db.ability_groups.iter().map(|group| &group.tags).collect(),
+    };
+
+    util::write_atomic(path, |file| {
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(hash)?;
+
+        ciborium::ser::into_writer(&snapshot, file)?;
+
+        Ok(())
+    })
+}
+
+pub fn load(path: &Path) -> anyhow::Result<(DB, [u8; 32])> {
+    let mut file = io::BufReader::new(fs::File::open(path)?);
+    let mut magic = [0u8; 4];
+    let mut version = [0u8; 4];
+    let mut hash = [0u8; 32];
+
+    file.read_exact(&mut magic)?;
+    file.read_exact(&mut version)?;
+
+    if &magic != MAGIC {
+        bail!("Not a PoxBase snapshot");
+    }
+
+    let version = u32::from_le_bytes(version);
+
+    if version != VERSION {
+        bail!("Unsupported snapshot version {}, expected {}", version, VERSION);
+    }
+
+    file.read_exact(&mut hash)?;
+
+    let Snapshot { mut db, effect_tags, group_tags } = ciborium::de::from_reader(file)
+        .context("Corrupted snapshot")?;
+
+    for (id, tags) in effect_tags.into_iter().enumerate() {
+        db.effects.get_mut(id as Id).context("Corrupted snapshot")?.tags = tags;
+    }
+    for (id, tags) in group_tags.into_iter().enumerate() {
+        db.ability_groups.get_mut(id as Id).context("Corrupted snapshot")?.tags = tags;
+    }
+
+    Ok((db, hash))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+    use crate::util::test::TempDir;
+
+    #[test]
+    fn round_trip() {
+        let mut db = parser::test::fixture();
+        let hash = [7u8; 32];
+        let dir = TempDir::with_files(&[]);
+        let path = dir.path().join("db.snapshot");
+
+        parser::create_search_index(&mut db);
+        save(&db, &hash, &path).unwrap();
+
+        let (loaded, loaded_hash) = load(&path).unwrap();
+        let tags = |tags: &Tags| tags.iter().collect::<Vec<_>>();
+
+        assert_eq!(loaded_hash, hash);
+        assert_eq!(dir.files(), ["db.snapshot"]);
+        assert_eq!(&*loaded.champs.get(2).unwrap().core.raw.name, "Flame Wraith");
+        assert_eq!(&*loaded.spells.get(50).unwrap().core.raw.name, "Fireball");
+
+        let burning = loaded.effects.get_by_key("burning").unwrap();
+
+        assert_eq!(&*burning.name, "Burning");
+        assert!(burning.tags.iter().next().is_some());
+        assert_eq!(tags(&burning.tags), tags(&db.effects.get_by_key("burning").unwrap().tags));
+
+        for (group, loaded_group) in db.ability_groups.iter().zip(loaded.ability_groups.iter()) {
+            assert_eq!(group.name, loaded_group.name);
+            assert_eq!(group.ranks, loaded_group.ranks);
+            assert_eq!(tags(&group.tags), tags(&loaded_group.tags));
+        }
+
+        for query in &["draconic", "flame wraith", "burning", "flankng", "fire damage"] {
+            assert!(!db.search_text(query).is_empty(), "{}", query);
+            assert_eq!(loaded.search.find(query), db.search.find(query), "{}", query);
+            assert_eq!(loaded.search_text(query), db.search_text(query), "{}", query);
+        }
+    }
+}
@@ -16,6 +16,7 @@
 
 use std::num::NonZeroU32;
 use std::fmt;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
 
 use crate::types::Id;
 
@@ -54,7 +55,7 @@ impl<T> Table<T> {
             let diff = 1 + id - self.index.len();
 
             self.index.reserve(diff);
-            self.index.extend(std::iter::repeat(None).take(diff));
+            self.index.extend(std::iter::repeat_n(None, diff));
         }
 
         let slot = match &mut self.index[item.id() as usize] {
@@ -78,6 +79,14 @@ impl<T> Table<T> {
         None
     }
 
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut T> {
+        if let Some(Some(slot)) = self.index.get(id as usize) {
+            return self.items.get_mut((slot.get() as usize) - 1);
+        }
+
+        None
+    }
+
     pub fn at(&self, slot: usize) -> Option<&T> {
         self.items.get(slot)
     }
@@ -89,8 +98,32 @@ impl<T> Table<T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.items.iter()
     }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.items.iter_mut()
+    }
 }
 
+impl<T: Serialize> Serialize for Table<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.items.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Table<T>
+where
+    T: TableItem + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut table = Table::default();
+
+        for item in Vec::<T>::deserialize(deserializer)? {
+            table.entry(item);
+        }
+
+        Ok(table)
+    }
+}
 
 pub trait TableItem {
     fn id(&self) -> Id;
@@ -0,0 +1,254 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Full-text search over descriptions and flavor text.
+//!
+//! Descriptions are indexed with the markdown links inserted by the parser
+//! stripped, so `[Fire](/effect/fire)` is indexed, and shown in snippets, as `Fire`.
+
+use std::borrow::Cow;
+use serde::Serialize;
+use rustc_hash::FxHashMap;
+
+use crate::db::{Score, SearchIndex, TableItem, DB};
+use crate::types::EntityId;
+
+/// Words of context shown in a snippet before the first match.
+const CONTEXT_BEFORE: usize = 6;
+
+/// Total number of words in a snippet.
+const SNIPPET_WORDS: usize = 24;
+
+/// Scores of matches in descriptions are divided by this, so they count for less
+/// than matches in names.
+const TEXT_WEIGHT: i32 = 2;
+
+/// Replace markdown links `[text](url)`, as well as `[text](*)`, with just the text.
+pub fn strip_links(desc: &str) -> Cow<'_, str> {
+    if !desc.contains('[') {
+        return Cow::Borrowed(desc);
+    }
+
+    let mut out = String::with_capacity(desc.len());
+    let mut rest = desc;
+
+    while let Some(open) = rest.find('[') {
+        // Text of a link ends at the first `]`, unless another `[` opens before it
+        let link = rest[open + 1..]
+            .find(['[', ']'])
+            .map(|close| open + 1 + close)
+            .filter(|&close| rest[close..].starts_with("]("))
+            .and_then(|close| Some((close, close + rest[close..].find(')')?)));
+
+        match link {
+            Some((close, end)) => {
+                out.push_str(&rest[..open]);
+                out.push_str(&rest[open + 1..close]);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[..=open]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Piece of a snippet, highlighted if it matched the query.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Fragment {
+    pub text: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub highlight: bool,
+}
+
+/// Cut a snippet out of `doc` around the first word matching the `query`.
+pub fn snippet(doc: &str, query: &str) -> Option<Vec<Fragment>> {
+    let query = SearchIndex::split_words(query).collect::<Vec<_>>();
+    let words = doc.split_whitespace().collect::<Vec<_>>();
+    let matched = words
+        .iter()
+        .map(|word| {
+            SearchIndex::split_words(word).any(|word| query.iter().any(|q| SearchIndex::matches(q, &word)))
+        })
+        .collect::<Vec<_>>();
+
+    let first = matched.iter().position(|&matched| matched)?;
+    let start = first.saturating_sub(CONTEXT_BEFORE);
+    let end = words.len().min(start + SNIPPET_WORDS);
+
+    let mut fragments = Vec::<Fragment>::new();
+
+    for i in start..end {
+        let highlight = matched[i];
+
+        match fragments.last_mut() {
+            Some(last) if last.highlight == highlight => {
+                last.text.push(' ');
+                last.text.push_str(words[i]);
+            }
+            // Keep the spaces between words out of the highlights
+            Some(last) if highlight => {
+                last.text.push(' ');
+                fragments.push(Fragment { text: words[i].into(), highlight });
+            }
+            Some(_) => fragments.push(Fragment { text: format!(" {}", words[i]), highlight }),
+            None => fragments.push(Fragment { text: words[i].into(), highlight }),
+        }
+    }
+
+    if start > 0 {
+        fragments.insert(0, Fragment { text: "… ".into(), highlight: false });
+    }
+    if end < words.len() {
+        fragments.push(Fragment { text: " …".into(), highlight: false });
+    }
+
+    Some(fragments)
+}
+
+impl DB {
+    /// All searchable text of an entity besides its name, with links stripped.
+    pub fn document(&self, eid: EntityId) -> Option<String> {
+        let parts: Vec<&str> = match eid {
+            EntityId::Champion(id) => vec![&self.champs.get(id)?.core.raw.description],
+            EntityId::Spell(id) => {
+                let spell = &self.spells.get(id)?.core;
+
+                vec![&spell.raw.description, &spell.flavor_text]
+            }
+            EntityId::Equip(id) => {
+                let equip = &self.equips.get(id)?.core;
+
+                vec![&equip.raw.description, &equip.flavor_text]
+            }
+            EntityId::Relic(id) => {
+                let relic = &self.relics.get(id)?.core;
+
+                vec![&relic.raw.description, &relic.flavor_text]
+            }
+            EntityId::AbilityGroup(id) => {
+                let mut descs = self
+                    .ability_groups
+                    .get(id)?
+                    .ranks
+                    .iter()
+                    .filter_map(|&id| Some(&*self.abilities.get(id)?.core.short_description))
+                    .collect::<Vec<_>>();
+
+                // Ranks often share the same description
+                descs.dedup();
+                descs
+            }
+            EntityId::Effect(id) => vec![&self.effects.get(id)?.desc],
+        };
+
+        let doc = parts
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .map(strip_links)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Some(doc)
+    }
+
+    /// All entities with text, in the order they are indexed.
+    pub(super) fn documents(&self) -> impl Iterator<Item = EntityId> + '_ {
+        let champs = self.champs.iter().map(|c| EntityId::Champion(c.id()));
+        let spells = self.spells.iter().map(|s| EntityId::Spell(s.id()));
+        let equips = self.equips.iter().map(|e| EntityId::Equip(e.id()));
+        let relics = self.relics.iter().map(|r| EntityId::Relic(r.id()));
+        let groups = self.ability_groups.iter().map(|g| EntityId::AbilityGroup(g.id()));
+        let effects = self.effects.iter().map(|e| EntityId::Effect(e.id));
+
+        champs.chain(spells).chain(equips).chain(relics).chain(groups).chain(effects)
+    }
+
+    pub fn create_text_index(&mut self) {
+        let mut text = SearchIndex::builder();
+
+        for eid in self.documents() {
+            if let Some(doc) = self.document(eid) {
+                text.insert(&doc, eid);
+            }
+        }
+
+        self.text = text.build();
+    }
+
+    /// Search names and descriptions, names weighing more. Returns entities with
+    /// their score and whether their description matched, best matches first.
+    pub fn search_text(&self, query: &str) -> Vec<(EntityId, Score, bool)> {
+        let mut scores = FxHashMap::default();
+
+        for (eid, score) in self.search.find(query) {
+            scores.insert(eid, (score, false));
+        }
+        for (eid, score) in self.text.find_text(query) {
+            // Typos in the description only count if the name didn't match
+            let entry = scores.entry(eid).or_insert((Score { typos: score.typos, points: 0 }, false));
+
+            entry.0.points += score.points / TEXT_WEIGHT;
+            entry.1 = true;
+        }
+
+        let mut sorted = scores
+            .into_iter()
+            .map(|(eid, (score, text))| (eid, score, text))
+            .collect::<Vec<_>>();
+
+        // Ties broken by entity, so pagination is stable
+        sorted.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        sorted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strip_and_snippet() {
+        let desc = "When this champion dies, adjacent enemies take 5 [Fire](/effect/fire) damage. [Broken](*";
+
+        assert_eq!(
+            strip_links(desc),
+            "When this champion dies, adjacent enemies take 5 Fire damage. [Broken](*"
+        );
+        assert_eq!(
+            strip_links("Deals [3 damage to [Fire](/effect/fire) units [] and [Frost](*)."),
+            "Deals [3 damage to Fire units [] and Frost."
+        );
+
+        let fragments = snippet(&strip_links(desc), "fire damage").unwrap();
+        let texts = fragments.iter().map(|f| (&*f.text, f.highlight)).collect::<Vec<_>>();
+
+        assert_eq!(
+            texts,
+            [
+                ("… ", false),
+                ("champion dies, adjacent enemies take 5 ", false),
+                ("Fire damage.", true),
+                (" [Broken](*", false),
+            ]
+        );
+        assert!(snippet("Nothing to see here", "fire").is_none());
+    }
+}
@@ -0,0 +1,408 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use rustc_hash::FxHashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{DB, RuneShim, EnumId, TableItem};
+use crate::cost::CostBreakdown;
+use crate::error::{InvalidDeckCode, InvalidUpgrade};
+use crate::types::{Id, Faction, RuneKind};
+
+/// Number of runes in a complete battlegroup.
+pub const DECK_SIZE: u32 = 30;
+
+/// A battlegroup, as a list of runes with counts.
+///
+/// Decks can be shared using a compact, URL-safe code, see `Deck::to_string` and
+/// `Deck::from_str`. Each entry is encoded as `{count}{kind}{id}`, where kind is
+/// one of `c`, `s`, `e` or `r`, and entries are separated by a `.`, for example
+/// `2c1204.1s312.3r77`. Champions with upgrades other than the defaults are
+/// followed by `-{id}` for each upgrade set, left empty to use the default of
+/// that set, such as `1c1204-201-311` or `1c1204--311`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Deck {
+    pub runes: Vec<DeckEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeckEntry {
+    pub kind: RuneKind,
+    pub id: Id,
+    #[serde(default = "default_count")]
+    pub count: u8,
+    /// Upgrade picked from each of the champion's `ability_sets`, `None` for the default.
+    #[serde(default, skip_serializing_if = "no_upgrades")]
+    pub upgrades: [Option<Id>; 2],
+}
+
+fn default_count() -> u8 {
+    1
+}
+
+fn no_upgrades(upgrades: &[Option<Id>; 2]) -> bool {
+    upgrades.iter().all(Option::is_none)
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Violation {
+    /// Rune doesn't exist in the current feed
+    #[serde(rename_all = "camelCase")]
+    UnknownRune { rune: RuneKind, id: Id },
+    /// Rune appears more times than its `deck_limit` allows
+    #[serde(rename_all = "camelCase")]
+    DeckLimit { rune: RuneKind, id: Id, count: u32, limit: u8 },
+    /// Rune isn't legal in ranked play
+    #[serde(rename_all = "camelCase")]
+    NotRanked { rune: RuneKind, id: Id },
+    /// Deck doesn't have exactly `DECK_SIZE` runes
+    #[serde(rename_all = "camelCase")]
+    DeckSize { size: u32, expected: u32 },
+    /// There is no single faction shared by all runes in the deck
+    NoCommonFaction,
+    /// Upgrade isn't in the corresponding upgrade set of the champion
+    #[serde(rename_all = "camelCase")]
+    InvalidUpgrade { id: Id, set: u8, ability: Id },
+    /// Upgrades are picked for a rune that isn't a champion
+    #[serde(rename_all = "camelCase")]
+    NotAChampion { rune: RuneKind, id: Id },
+}
+
+/// Entry of a deck, with the nora cost of champions reflecting their upgrades.
+#[derive(Serialize, Debug)]
+pub struct ResolvedEntry<'a> {
+    #[serde(flatten)]
+    pub rune: RuneShim<'a>,
+    pub count: u8,
+    /// Upgrades of a champion, with defaults filled in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrades: Option<[Option<Id>; 2]>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Report<'a> {
+    pub code: String,
+    pub runes: Vec<ResolvedEntry<'a>>,
+    pub size: u32,
+    pub nora_cost: u32,
+    pub factions: Vec<Faction>,
+    pub violations: Vec<Violation>,
+}
+
+impl Deck {
+    /// Merge duplicate entries and drop empty ones, ordering by kind and id.
+    /// Upgrades matching the champion's defaults are reset to `None`, so that
+    /// copies are only kept apart when their upgrades actually differ.
+    pub fn normalize(&mut self, db: &DB) {
+        self.runes.retain(|entry| entry.count > 0);
+
+        for entry in &mut self.runes {
+            let champ = match entry.kind {
+                RuneKind::Champion => db.champs.get(entry.id),
+                _ => None,
+            };
+
+            if let Some(champ) = champ {
+                for (pick, default) in entry.upgrades.iter_mut().zip(&champ.defaults) {
+                    if *pick == Some(*default) {
+                        *pick = None;
+                    }
+                }
+            }
+        }
+
+        self.runes.sort_unstable_by_key(|entry| (entry.kind, entry.id, entry.upgrades));
+        self.runes.dedup_by(|next, prev| {
+            if next.kind == prev.kind && next.id == prev.id && next.upgrades == prev.upgrades {
+                prev.count = prev.count.saturating_add(next.count);
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    pub fn validate<'a>(&self, db: &'a DB) -> Report<'a> {
+        let mut violations = Vec::new();
+        let mut runes = Vec::with_capacity(self.runes.len());
+        let mut size = 0;
+        let mut nora_cost = 0;
+        let mut faction_counts = [0u32; Faction::SIZE];
+        let mut distinct = 0;
+
+        // Copies of a champion with different upgrades are separate entries,
+        // but they all count towards the same deck limit.
+        let mut copies = FxHashMap::default();
+
+        for entry in &self.runes {
+            *copies.entry((entry.kind, entry.id)).or_insert(0) += entry.count as u32;
+        }
+
+        for entry in &self.runes {
+            let (kind, id) = (entry.kind, entry.id);
+            let count = entry.count as u32;
+
+            size += count;
+
+            // Only check each rune once, on its first entry
+            let total = copies.remove(&(kind, id));
+
+            let view = match db.rune(kind, id) {
+                Some(view) => view,
+                None => {
+                    if total.is_some() {
+                        violations.push(Violation::UnknownRune { rune: kind, id });
+                    }
+                    continue;
+                }
+            };
+            let raw = view.raw;
+
+            if let Some(total) = total {
+                if total > raw.deck_limit as u32 {
+                    violations.push(Violation::DeckLimit { rune: kind, id, count: total, limit: raw.deck_limit });
+                }
+                if !raw.allow_ranked {
+                    violations.push(Violation::NotRanked { rune: kind, id });
+                }
+                for faction in view.factions {
+                    faction_counts[faction.id() as usize] += 1;
+                }
+
+                distinct += 1;
+            }
+
+            let mut rune = view.shim();
+            let upgrades = match view.champ {
+                Some(champ) => match champ.picks(entry.upgrades) {
+                    Ok(picks) => {
+                        if let Some(cost) = CostBreakdown::new(db, champ, picks) {
+                            rune.nora_cost = cost.total.max(0) as u16;
+                        }
+
+                        Some(picks)
+                    }
+                    Err(err) => {
+                        if let InvalidUpgrade::WrongSet(ability, set) = err {
+                            violations.push(Violation::InvalidUpgrade { id, set, ability });
+                        }
+
+                        None
+                    }
+                },
+                None => {
+                    if !no_upgrades(&entry.upgrades) {
+                        violations.push(Violation::NotAChampion { rune: kind, id });
+                    }
+
+                    None
+                }
+            };
+
+            nora_cost += rune.nora_cost as u32 * count;
+            runes.push(ResolvedEntry {
+                rune,
+                count: entry.count,
+                upgrades,
+            });
+        }
+
+        if size != DECK_SIZE {
+            violations.push(Violation::DeckSize { size, expected: DECK_SIZE });
+        }
+
+        let factions = faction_counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count == distinct)
+            .filter_map(|(id, _)| Faction::from_id(id as Id))
+            .collect::<Vec<_>>();
+
+        if factions.is_empty() && !runes.is_empty() {
+            violations.push(Violation::NoCommonFaction);
+        }
+
+        Report {
+            code: self.to_string(),
+            runes,
+            size,
+            nora_cost,
+            factions,
+            violations,
+        }
+    }
+}
+
+impl RuneKind {
+    fn code(self) -> char {
+        match self {
+            RuneKind::Champion => 'c',
+            RuneKind::Spell => 's',
+            RuneKind::Equip => 'e',
+            RuneKind::Relic => 'r',
+        }
+    }
+
+    fn from_code(code: char) -> Option<Self> {
+        match code {
+            'c' => Some(RuneKind::Champion),
+            's' => Some(RuneKind::Spell),
+            'e' => Some(RuneKind::Equip),
+            'r' => Some(RuneKind::Relic),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Deck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, entry) in self.runes.iter().enumerate() {
+            if i != 0 {
+                f.write_char('.')?;
+            }
+            write!(f, "{}{}{}", entry.count, entry.kind.code(), entry.id)?;
+
+            // Only champions have upgrades
+            let picked = match entry.kind {
+                RuneKind::Champion => entry.upgrades.iter().rposition(Option::is_some).map_or(0, |last| last + 1),
+                _ => 0,
+            };
+
+            for pick in &entry.upgrades[..picked] {
+                f.write_char('-')?;
+
+                if let Some(id) = pick {
+                    write!(f, "{}", id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Deck {
+    type Err = InvalidDeckCode;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let mut deck = Deck::default();
+
+        for part in code.split('.').filter(|part| !part.is_empty()) {
+            let split = part.find(|ch: char| !ch.is_ascii_digit()).ok_or(InvalidDeckCode)?;
+            let (count, rest) = part.split_at(split);
+            let mut chars = rest.chars();
+            let kind = chars.next().and_then(RuneKind::from_code).ok_or(InvalidDeckCode)?;
+            let mut ids = chars.as_str().split('-');
+            let id = ids.next().unwrap_or_default().parse().map_err(|_| InvalidDeckCode)?;
+            let mut upgrades = [None, None];
+
+            for (set, pick) in ids.enumerate() {
+                if set >= upgrades.len() || kind != RuneKind::Champion {
+                    return Err(InvalidDeckCode);
+                }
+                if !pick.is_empty() {
+                    upgrades[set] = Some(pick.parse().map_err(|_| InvalidDeckCode)?);
+                }
+            }
+
+            deck.runes.push(DeckEntry {
+                kind,
+                id,
+                count: count.parse().map_err(|_| InvalidDeckCode)?,
+                upgrades,
+            });
+        }
+
+        Ok(deck)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deck_code() {
+        let mut deck = Deck {
+            runes: vec![
+                DeckEntry { kind: RuneKind::Relic, id: 77, count: 3, upgrades: [None, None] },
+                DeckEntry { kind: RuneKind::Champion, id: 1204, count: 1, upgrades: [None, None] },
+                DeckEntry { kind: RuneKind::Spell, id: 312, count: 1, upgrades: [None, None] },
+                DeckEntry { kind: RuneKind::Champion, id: 1204, count: 1, upgrades: [None, None] },
+                DeckEntry { kind: RuneKind::Equip, id: 5, count: 0, upgrades: [None, None] },
+            ],
+        };
+
+        deck.normalize(&DB::default());
+
+        let code = deck.to_string();
+
+        assert_eq!(code, "2c1204.1s312.3r77");
+        assert_eq!(code.parse::<Deck>().unwrap(), deck);
+        assert_eq!("".parse::<Deck>().unwrap(), Deck::default());
+
+        assert!("c1204".parse::<Deck>().is_err());
+        assert!("2x1204".parse::<Deck>().is_err());
+        assert!("2c".parse::<Deck>().is_err());
+        assert!("2c12a".parse::<Deck>().is_err());
+    }
+
+    #[test]
+    fn deck_code_upgrades() {
+        let mut deck = Deck {
+            runes: vec![
+                DeckEntry { kind: RuneKind::Champion, id: 1204, count: 1, upgrades: [None, Some(311)] },
+                DeckEntry { kind: RuneKind::Champion, id: 1204, count: 1, upgrades: [Some(201), Some(311)] },
+                DeckEntry { kind: RuneKind::Champion, id: 1204, count: 1, upgrades: [None, Some(311)] },
+                DeckEntry { kind: RuneKind::Champion, id: 99, count: 1, upgrades: [Some(201), None] },
+            ],
+        };
+
+        deck.normalize(&DB::default());
+
+        let code = deck.to_string();
+
+        assert_eq!(code, "1c99-201.2c1204--311.1c1204-201-311");
+        assert_eq!(code.parse::<Deck>().unwrap(), deck);
+
+        assert!("1c1204-201-311-401".parse::<Deck>().is_err());
+        assert!("1s312-201".parse::<Deck>().is_err());
+        assert!("1c1204-x".parse::<Deck>().is_err());
+    }
+
+    #[test]
+    fn upgrades_share_deck_limit() {
+        let db = crate::parser::test::fixture();
+        let mut deck: Deck = "1c1-201.1c1.1c1-211.1c1-211-301.1c2".parse().unwrap();
+
+        deck.normalize(&db);
+
+        // Picking the defaults is the same as picking nothing
+        assert_eq!(deck.to_string(), "2c1.2c1-211.1c2");
+
+        let report = deck.validate(&db);
+        let limits = report.violations.iter().filter(|v| matches!(v, Violation::DeckLimit { .. })).collect::<Vec<_>>();
+
+        assert_eq!(limits, [&Violation::DeckLimit { rune: RuneKind::Champion, id: 1, count: 4, limit: 3 }]);
+        assert!(matches!(report.factions[..], [Faction::KthirForest]));
+        assert_eq!(report.runes[0].upgrades, Some([Some(201), Some(301)]));
+        assert_eq!(report.runes[1].upgrades, Some([Some(211), Some(301)]));
+    }
+}
@@ -17,6 +17,8 @@
 use actix_web::{http, HttpResponse, ResponseError};
 use thiserror::Error;
 
+use crate::types::Id;
+
 #[derive(Debug, Error)]
 #[error("Not Found")]
 pub struct NotFound;
@@ -32,3 +34,58 @@ impl ResponseError for NotFound {
             .body(r#"{"error":"Item not found"}"#)
     }
 }
+
+#[derive(Debug, Error)]
+#[error("Invalid deck code")]
+pub struct InvalidDeckCode;
+
+impl ResponseError for InvalidDeckCode {
+    fn status_code(&self) -> http::StatusCode {
+        http::StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::BadRequest()
+            .content_type("application/json")
+            .body(r#"{"error":"Invalid deck code"}"#)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Unsupported image format")]
+pub struct UnsupportedFormat;
+
+impl ResponseError for UnsupportedFormat {
+    fn status_code(&self) -> http::StatusCode {
+        http::StatusCode::NOT_IMPLEMENTED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::NotImplemented()
+            .content_type("application/json")
+            .body(r#"{"error":"Unsupported image format"}"#)
+    }
+}
+
+/// Upgrade abilities selected for a champion don't match its ability sets.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidUpgrade {
+    #[error("Ability {0} is not an upgrade of this champion")]
+    NotAnUpgrade(Id),
+    #[error("Abilities {0} and {1} are picked from the same upgrade set")]
+    SameSet(Id, Id),
+    #[error("Ability {0} is not in upgrade set {1} of this champion")]
+    WrongSet(Id, u8),
+}
+
+impl ResponseError for InvalidUpgrade {
+    fn status_code(&self) -> http::StatusCode {
+        http::StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::BadRequest()
+            .content_type("application/json")
+            .body(serde_json::json!({ "error": self.to_string() }).to_string())
+    }
+}
@@ -0,0 +1,371 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::db::{snapshot, TableItem, DB};
+use crate::parser::Hash;
+use crate::types::{Id, Champion, RuneKind, Size};
+use crate::util::{self, hex};
+
+const CHANGELOG: &str = "changelog.json";
+
+/// A single difference between two consecutive versions of the feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Change {
+    Added {
+        rune: RuneKind,
+        id: Id,
+        name: Box<str>,
+    },
+    Removed {
+        rune: RuneKind,
+        id: Id,
+        name: Box<str>,
+    },
+    NoraCost {
+        rune: RuneKind,
+        id: Id,
+        name: Box<str>,
+        from: u16,
+        to: u16,
+    },
+    /// Stat change on a champion
+    Stat {
+        id: Id,
+        name: Box<str>,
+        stat: Stat,
+        from: u16,
+        to: u16,
+    },
+    /// Size change on a champion
+    Size {
+        id: Id,
+        name: Box<str>,
+        from: Size,
+        to: Size,
+    },
+    /// Abilities swapped in one of the two upgrade sets of a champion
+    Abilities {
+        id: Id,
+        name: Box<str>,
+        set: u8,
+        added: Vec<AbilityRef>,
+        removed: Vec<AbilityRef>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Stat {
+    MaxRng,
+    MinRng,
+    Defense,
+    Speed,
+    Damage,
+    HitPoints,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AbilityRef {
+    pub id: Id,
+    pub name: Box<str>,
+}
+
+impl Change {
+    /// Champion this change refers to, if any.
+    pub fn champion(&self) -> Option<Id> {
+        match *self {
+            Change::Added { rune: RuneKind::Champion, id, .. }
+            | Change::Removed { rune: RuneKind::Champion, id, .. }
+            | Change::NoraCost { rune: RuneKind::Champion, id, .. }
+            | Change::Stat { id, .. }
+            | Change::Size { id, .. }
+            | Change::Abilities { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// All changes between a version of the feed and the one preceding it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Changelog {
+    pub version: u32,
+    pub hash: String,
+    pub previous: String,
+    pub timestamp: u64,
+    pub changes: Vec<Change>,
+}
+
+/// Changelogs of all feed versions seen so far, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct History {
+    pub versions: Vec<Changelog>,
+}
+
+impl History {
+    /// Load the changelogs recorded in `dir`. A missing changelog is an empty history,
+    /// but one that can't be read is an error, as recording over it would lose every
+    /// version recorded so far.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        match fs::File::open(dir.join(CHANGELOG)) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(History::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Record a new version of the feed, archiving its snapshot in `dir`. Only the
+    /// snapshots of the last `keep` versions, at least one, are kept. Changelogs are kept forever.
+    pub fn record(
+        &mut self,
+        dir: &Path,
+        keep: u32,
+        old: &DB,
+        old_hash: &Hash,
+        new: &DB,
+        new_hash: &Hash,
+    ) -> anyhow::Result<&Changelog> {
+        let version = self.versions.last().map(|log| log.version + 1).unwrap_or(1);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        fs::create_dir_all(dir)?;
+        snapshot::save(new, new_hash, &Self::snapshot_path(dir, version, new_hash))?;
+
+        if let Err(err) = Self::prune_snapshots(dir, version.saturating_sub(keep.max(1))) {
+            log::warn!("⚠️  Couldn't remove old snapshots from {}: {}", dir.display(), err);
+        }
+
+        self.versions.push(Changelog {
+            version,
+            hash: hex(new_hash),
+            previous: hex(old_hash),
+            timestamp,
+            changes: diff(old, new),
+        });
+
+        let written = util::write_atomic(&dir.join(CHANGELOG), |file| {
+            serde_json::to_writer(file, &*self).map_err(io::Error::from)
+        });
+
+        // Keep the history in memory in line with the changelog on disk
+        if let Err(err) = written {
+            self.versions.pop();
+            return Err(err.into());
+        }
+
+        Ok(self.versions.last().unwrap())
+    }
+
+    pub fn snapshot_path(dir: &Path, version: u32, hash: &Hash) -> PathBuf {
+        dir.join(format!("{:05}-{}.snapshot", version, &hex(hash)[..16]))
+    }
+
+    /// Remove archived snapshots of all versions up to and including `last`.
+    fn prune_snapshots(dir: &Path, last: u32) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some("snapshot") {
+                continue;
+            }
+
+            let version = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.split('-').next())
+                .and_then(|version| version.parse::<u32>().ok());
+
+            if version.is_some_and(|version| version <= last) {
+                fs::remove_file(&path)?;
+                log::info!("🗑️  Removed {}", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn rune_ids(db: &DB, kind: RuneKind) -> Vec<Id> {
+    match kind {
+        RuneKind::Champion => db.champs.iter().map(TableItem::id).collect(),
+        RuneKind::Spell => db.spells.iter().map(TableItem::id).collect(),
+        RuneKind::Equip => db.equips.iter().map(TableItem::id).collect(),
+        RuneKind::Relic => db.relics.iter().map(TableItem::id).collect(),
+    }
+}
+
+fn ability_refs<'a>(db: &'a DB, ids: impl Iterator<Item = &'a Id> + 'a) -> Vec<AbilityRef> {
+    ids.map(|&id| AbilityRef {
+        id,
+        name: db.abilities.get(id).map(|a| a.core.name.clone()).unwrap_or_default(),
+    })
+    .collect()
+}
+
+fn diff_champ(old_db: &DB, old: &Champion, new_db: &DB, new: &Champion, changes: &mut Vec<Change>) {
+    let (o, n) = (&old.core, &new.core);
+    let id = new.id();
+    let name = &n.raw.name;
+
+    let stats = [
+        (Stat::MaxRng, o.max_rng as u16, n.max_rng as u16),
+        (Stat::MinRng, o.min_rng as u16, n.min_rng as u16),
+        (Stat::Defense, o.defense as u16, n.defense as u16),
+        (Stat::Speed, o.speed as u16, n.speed as u16),
+        (Stat::Damage, o.damage, n.damage),
+        (Stat::HitPoints, o.hit_points, n.hit_points),
+    ];
+
+    for &(stat, from, to) in &stats {
+        if from != to {
+            changes.push(Change::Stat { id, name: name.clone(), stat, from, to });
+        }
+    }
+
+    if o.size != n.size {
+        changes.push(Change::Size { id, name: name.clone(), from: o.size, to: n.size });
+    }
+
+    for set in 0..2 {
+        let (old_set, new_set) = (&old.ability_sets[set], &new.ability_sets[set]);
+        let removed = old_set.iter().filter(|id| !new_set.contains(id));
+        let added = new_set.iter().filter(|id| !old_set.contains(id));
+
+        let removed = ability_refs(old_db, removed);
+        let added = ability_refs(new_db, added);
+
+        if !added.is_empty() || !removed.is_empty() {
+            changes.push(Change::Abilities { id, name: name.clone(), set: set as u8, added, removed });
+        }
+    }
+}
+
+/// Compute all changes between two versions of the feed.
+pub fn diff(old: &DB, new: &DB) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for &rune in &[RuneKind::Champion, RuneKind::Spell, RuneKind::Equip, RuneKind::Relic] {
+        for id in rune_ids(old, rune) {
+            if new.rune(rune, id).is_none() {
+                let name = old.rune(rune, id).unwrap().raw.name.clone();
+
+                changes.push(Change::Removed { rune, id, name });
+            }
+        }
+
+        for id in rune_ids(new, rune) {
+            let next = new.rune(rune, id).unwrap();
+            let name = &next.raw.name;
+
+            let prev = match old.rune(rune, id) {
+                Some(prev) => prev,
+                None => {
+                    changes.push(Change::Added { rune, id, name: name.clone() });
+                    continue;
+                }
+            };
+
+            let (from, to) = (prev.raw.nora_cost, next.raw.nora_cost);
+
+            if from != to {
+                changes.push(Change::NoraCost { rune, id, name: name.clone(), from, to });
+            }
+
+            if let (Some(prev), Some(next)) = (prev.champ, next.champ) {
+                diff_champ(old, prev, new, next, &mut changes);
+            }
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use crate::parser::test::{fixture, fixture_with};
+    use crate::util::test::TempDir;
+
+    fn ability(db: &DB, id: Id) -> AbilityRef {
+        AbilityRef { id, name: db.abilities.get(id).unwrap().core.name.clone() }
+    }
+
+    #[test]
+    fn diff_versions() {
+        let old = fixture();
+        let new = fixture_with(|feed| {
+            let champs = feed["champs"].as_array_mut().unwrap();
+            let mut veteran = champs.remove(3);
+
+            veteran["id"] = json!(6);
+            veteran["name"] = json!("Ironfist Veteran");
+            champs.push(veteran);
+
+            champs[0]["noraCost"] = json!(65);
+            champs[1]["damage"] = json!(9);
+            champs[1]["size"] = json!("2x2");
+            champs[2]["abilitySets"][0]["abilities"][0] = champs[0]["abilitySets"][0]["abilities"][1].clone();
+
+            let spells = feed["spells"].as_array_mut().unwrap();
+
+            spells[0]["id"] = json!(51);
+            spells[0]["name"] = json!("Frostbolt");
+
+            feed["relics"][0]["noraCost"] = json!(55);
+        });
+
+        let name = |name: &str| Box::<str>::from(name);
+
+        assert_eq!(diff(&old, &old), []);
+        assert_eq!(
+            diff(&old, &new),
+            [
+                Change::Removed { rune: RuneKind::Champion, id: 4, name: name("Ironfist Grunt") },
+                Change::NoraCost { rune: RuneKind::Champion, id: 1, name: name("Draconic Guard"), from: 60, to: 65 },
+                Change::Stat { id: 2, name: name("Flame Wraith"), stat: Stat::Damage, from: 8, to: 9 },
+                Change::Size { id: 2, name: name("Flame Wraith"), from: Size::OneByOne, to: Size::TwoByTwo },
+                Change::Abilities {
+                    id: 3,
+                    name: name("K'thir Hunter"),
+                    set: 0,
+                    added: vec![ability(&new, 211)],
+                    removed: vec![ability(&old, 402)],
+                },
+                Change::Added { rune: RuneKind::Champion, id: 6, name: name("Ironfist Veteran") },
+                Change::Removed { rune: RuneKind::Spell, id: 50, name: name("Fireball") },
+                Change::Added { rune: RuneKind::Spell, id: 51, name: name("Frostbolt") },
+                Change::NoraCost { rune: RuneKind::Relic, id: 70, name: name("Tower"), from: 50, to: 55 },
+            ],
+        );
+    }
+
+    #[test]
+    fn prune_snapshots() {
+        let dir = TempDir::with_files(&["00001-aa.snapshot", "00002-bb.snapshot", "00003-cc.snapshot", "changelog.json"]);
+
+        History::prune_snapshots(dir.path(), 2).unwrap();
+
+        assert_eq!(dir.files(), ["00003-cc.snapshot", "changelog.json"]);
+    }
+}
@@ -1,39 +1,173 @@
-use std::{fs, io};
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::Cursor;
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoder for the `.pep` images shipped with the PoxNora client.
+//!
+//! A `.pep` file starts with a big-endian `u16` length, followed by a header of
+//! that many bytes, followed by the scrambled image. The image is scrambled by
+//! splitting it into three equal blocks and swapping the first two, leaving any
+//! remainder in place. The header isn't needed for decoding, in the files we've
+//! seen it holds the sorted characters of the original file name, such as
+//! `__bcdginnnooprtu` for `icon_dot_burn` and `png`.
 
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail};
 use image::ImageFormat;
-use image::io::Reader as ImageReader;
-// use image::codecs::dds::DdsDecoder;
 
-pub fn run() -> anyhow::Result<()> {
-    let mut icon = fs::File::open("./icon_dot_burn.pep")?;
-    let mut bytes = Vec::new();
+pub struct Pep<'a> {
+    pub header: &'a [u8],
+    pub body: &'a [u8],
+}
 
-    icon.read_to_end(&mut bytes)?;
+impl<'a> Pep<'a> {
+    pub fn parse(bytes: &'a [u8]) -> anyhow::Result<Self> {
+        if bytes.len() < 2 {
+            bail!("File too short");
+        }
 
-    let len = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
-    let head = std::str::from_utf8(&bytes[2..2 + len])?;
+        let len = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
 
+        if bytes.len() < 2 + len {
+            bail!("Header length {} exceeds file size", len);
+        }
 
+        Ok(Pep {
+            header: &bytes[2..2 + len],
+            body: &bytes[2 + len..],
+        })
+    }
 
-    println!("head {:?}", head);
+    /// Swap the first two thirds of the body back into place and detect the format
+    /// of the image.
+    pub fn unscramble(&self) -> anyhow::Result<(Vec<u8>, ImageFormat)> {
+        let third = self.body.len() / 3;
+        let mut out = Vec::with_capacity(self.body.len());
+
+        out.extend_from_slice(&self.body[third..third * 2]);
+        out.extend_from_slice(&self.body[..third]);
+        out.extend_from_slice(&self.body[third * 2..]);
+
+        let format = image::guess_format(&out).map_err(|_| anyhow!("Unrecognized image format"))?;
+
+        Ok((out, format))
+    }
+}
 
-    let mut body = Vec::new();
-    body.extend_from_slice(&bytes[2+len..]);
+/// Decode a `.pep` file into a PNG.
+pub fn pep_to_png(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let pep = Pep::parse(bytes)?;
+    let (bytes, format) = pep.unscramble()?;
 
-    for (byte, key) in body.iter_mut().zip(head.bytes()) {
-        *byte = byte.wrapping_sub(key);
+    if format == ImageFormat::Png {
+        return Ok(bytes);
     }
 
-    println!("rest {:?} {:?}", String::from_utf8_lossy(&body[..20]), &body[..20]);
+    let mut png = Vec::new();
+
+    image::load_from_memory_with_format(&bytes, format)?
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
 
-    // let img = ImageReader::new(Cursor::new(body)).decode()?;
+    Ok(png)
+}
+
+/// Decode `.pep` files into PNGs, placed in `out` or next to the source files.
+pub fn run(files: &[PathBuf], out: Option<&Path>) -> anyhow::Result<()> {
+    if let Some(dir) = out {
+        fs::create_dir_all(dir)?;
+    }
 
-    // let img = ImageReader::open("./icon_dot_burn.pep")?.decode()?;
+    for path in files {
+        let name = path.with_extension("png");
+        let target = match (out, name.file_name()) {
+            (Some(dir), Some(file)) => dir.join(file),
+            (Some(_), None) => bail!("{}: Not a file name", path.display()),
+            (None, _) => name,
+        };
+        let png = pep_to_png(&fs::read(path)?).map_err(|err| anyhow!("{}: {}", path.display(), err))?;
 
-    // println!(">>> {:?}", img.to_rgb());
+        fs::write(&target, png)?;
+
+        log::info!("🖼️  {} -> {}", path.display(), target.display());
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Scramble an image the way the client does.
+    fn scramble(header: &[u8], image: &[u8]) -> Vec<u8> {
+        let third = image.len() / 3;
+        let mut bytes = (header.len() as u16).to_be_bytes().to_vec();
+
+        bytes.extend_from_slice(header);
+        bytes.extend_from_slice(&image[third..third * 2]);
+        bytes.extend_from_slice(&image[..third]);
+        bytes.extend_from_slice(&image[third * 2..]);
+        bytes
+    }
+
+    #[test]
+    fn decode_icon() {
+        let bytes = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/icon_dot_burn.pep")).unwrap();
+        let pep = Pep::parse(&bytes).unwrap();
+
+        assert_eq!(pep.header, b"__bcdginnnooprtu");
+
+        let (_, format) = pep.unscramble().unwrap();
+        let image = image::load_from_memory(&pep_to_png(&bytes).unwrap()).unwrap();
+
+        assert_eq!(format, ImageFormat::Png);
+        assert_eq!((image.width(), image.height()), (56, 56));
+    }
+
+    #[test]
+    fn decode_gif() {
+        let mut gif = Vec::new();
+
+        image::DynamicImage::new_rgba8(5, 3)
+            .write_to(&mut Cursor::new(&mut gif), ImageFormat::Gif)
+            .unwrap();
+
+        // Make sure the remainder after the three blocks is left in place
+        gif.push(0x3b);
+        if gif.len() % 3 == 0 {
+            gif.push(0x3b);
+        }
+
+        let bytes = scramble(b"ab", &gif);
+        let (unscrambled, format) = Pep::parse(&bytes).unwrap().unscramble().unwrap();
+
+        assert_eq!(unscrambled, gif);
+        assert_eq!(format, ImageFormat::Gif);
+
+        let png = image::load_from_memory_with_format(&pep_to_png(&bytes).unwrap(), ImageFormat::Png).unwrap();
+
+        assert_eq!((png.width(), png.height()), (5, 3));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Pep::parse(b"\0").is_err());
+        assert!(Pep::parse(b"\0\x05abc").is_err());
+        assert!(Pep::parse(&scramble(b"", b"not an image")).unwrap().unscramble().is_err());
+        assert!(run(&[PathBuf::from("..")], Some(Path::new(env!("CARGO_MANIFEST_DIR")))).is_err());
+    }
+}
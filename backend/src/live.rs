@@ -0,0 +1,58 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::Deref;
+use std::sync::Arc;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web::Data;
+use actix_web::{Error, FromRequest, HttpRequest};
+use arc_swap::ArcSwap;
+use futures::future::{err, ok, Ready};
+
+/// Shared handle to a value that can be replaced while the server is running.
+pub type Handle<T> = Data<ArcSwap<T>>;
+
+pub fn handle<T>(value: T) -> Handle<T> {
+    Data::new(ArcSwap::from_pointee(value))
+}
+
+/// Extractor for the current value behind a `Handle<T>`.
+///
+/// The value is pinned for the duration of the request, so swapping the handle
+/// never affects requests that are already in flight.
+pub struct Live<T>(Arc<T>);
+
+impl<T> Deref for Live<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: 'static> FromRequest for Live<T> {
+    type Config = ();
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match req.app_data::<Handle<T>>() {
+            Some(handle) => ok(Live(handle.load_full())),
+            None => err(ErrorInternalServerError("Live data is not configured")),
+        }
+    }
+}
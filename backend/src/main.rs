@@ -15,32 +15,52 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
-use actix_web::web::{Data, Path};
+use actix_web::web::{self, Data, Json, Path, Query};
 use actix_web::{
-    dev, get, middleware, rt::time, rt::System, web, App, Error, HttpResponse, HttpServer,
+    dev, get, middleware, post, rt::time, rt::System, App, Error, HttpRequest, HttpResponse,
+    HttpServer,
 };
-use clap::Parser;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
 use futures::{
     future::{select, Either},
     pin_mut,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use simple_logger::SimpleLogger;
 
 mod assets;
+mod compare;
+mod cost;
 mod db;
+mod deck;
 mod error;
+mod history;
+mod live;
+mod manifest;
 mod parser;
 mod types;
-
-use crate::db::{SearchId, Searchable, DB};
-use crate::error::NotFound;
+mod util;
+mod variants;
+
+use crate::assets::AssetDir;
+use crate::compare::{Comparison, CompareQuery};
+use crate::cost::{CostBreakdown, CostQuery};
+use crate::db::{snapshot, Fragment, Popularity, Ranking, RuneQuery, TypeaheadQuery, DB};
+use crate::parser::Hash;
+use crate::deck::Deck;
+use crate::history::{Change, History};
+use crate::live::{Handle, Live};
+use crate::error::{NotFound, UnsupportedFormat};
+use crate::variants::{Format, Variants, VariantQuery};
 use crate::types::{
-    Ability, AbilityGroup, Champion, Effect, EntityId, Equip, Id, Rarity, Relic, Rune, Shim, Spell,
+    Ability, AbilityGroup, Champion, Effect, EntityShim, Equip, Id, Relic, Rune, Shim, Spell,
 };
 
 #[derive(Parser)]
@@ -60,8 +80,71 @@ struct Opts {
     #[clap(long = "listen", default_value = "127.0.0.1:8000")]
     socket: SocketAddr,
     /// Don't verify whether all assets have been downloaded
-    #[clap(long = "no-assets")]
+    #[clap(long = "no-assets", conflicts_with = "assets-only")]
     no_assets: bool,
+    /// Download all missing assets for the current feed and exit without starting the server
+    #[clap(long = "assets-only")]
+    assets_only: bool,
+    /// After syncing assets, delete art that isn't used by any rune or ability in the feed anymore
+    #[clap(long = "prune", conflicts_with = "no-assets")]
+    prune: bool,
+    /// Directory assets are mirrored to
+    #[clap(long = "assets", default_value = assets::ASSETS)]
+    assets: PathBuf,
+    /// Base URL of the CDN, or a caching mirror of it, to download assets from
+    #[clap(long = "cdn", default_value = assets::CDN)]
+    cdn: String,
+    /// Comma separated widths of thumbnails generated for rune art, in addition to WebP encodings
+    #[clap(long = "thumbnail-sizes", default_value = "128,256", value_delimiter = ',')]
+    thumbnail_sizes: Vec<u32>,
+    /// Maximum number of assets downloaded from the CDN at the same time
+    #[clap(long = "download-concurrency", default_value = "16")]
+    download_concurrency: usize,
+    /// How many times to retry a download that failed with a transient error, backing off exponentially
+    #[clap(long = "download-retries", default_value = "3")]
+    download_retries: u32,
+    /// Where to load the feed from. This can be either a URL or a path to a local JSON file, which is useful
+    /// when running offline.
+    #[clap(long = "feed", default_value = parser::FEED_URL)]
+    feed: parser::Source,
+    /// Path to the snapshot of the last parsed feed. The server starts from the snapshot if one exists, and
+    /// refreshes it in the background whenever the feed changes.
+    #[clap(long = "snapshot", default_value = "poxbase.snapshot")]
+    snapshot: PathBuf,
+    /// Don't read or write snapshots
+    #[clap(long = "no-snapshot")]
+    no_snapshot: bool,
+    /// Directory where snapshots of every version of the feed, and the changelog between them, are kept.
+    #[clap(long = "history", default_value = "history")]
+    history: PathBuf,
+    /// Don't keep track of changes between versions of the feed
+    #[clap(long = "no-history")]
+    no_history: bool,
+    /// Number of the most recent versions of the feed to keep snapshots of in the history directory.
+    #[clap(long = "history-snapshots", default_value = "20")]
+    history_snapshots: u32,
+    /// JSON file with weights of the signals used to rank search results, such as the kind of entity and
+    /// whether a rune is legal in ranked play. Defaults are used for anything not in the file.
+    #[clap(long = "ranking")]
+    ranking: Option<PathBuf>,
+    /// JSON file listing how popular runes, abilities and conditions are, to rank popular ones higher.
+    #[clap(long = "popularity")]
+    popularity: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode .pep images from the PoxNora client into PNGs and exit
+    DecodePep {
+        /// Files to decode
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+        /// Directory to write PNGs to, defaults to the directory of each file
+        #[clap(long = "out")]
+        out: Option<PathBuf>,
+    },
 }
 
 fn json<S: Serialize>(ser: &S) -> Result<HttpResponse, Error> {
@@ -71,7 +154,7 @@ fn json<S: Serialize>(ser: &S) -> Result<HttpResponse, Error> {
 }
 
 #[get("/init")]
-async fn get_init(db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_init(db: Live<DB>) -> Result<HttpResponse, Error> {
     #[derive(Serialize)]
     struct Response<'a> {
         expansions: &'a [Shim<'a>],
@@ -89,89 +172,74 @@ async fn get_init(db: Data<DB>) -> Result<HttpResponse, Error> {
 }
 
 #[get("/typeahead/{query}")]
-async fn get_typeahead(query: Path<String>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_typeahead(
+    text: Path<String>,
+    query: Query<TypeaheadQuery>,
+    db: Live<DB>,
+) -> Result<HttpResponse, Error> {
+    json(&db.typeahead(&text, &query))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[get("/search")]
+async fn get_search(query: Query<SearchQuery>, db: Live<DB>) -> Result<HttpResponse, Error> {
     #[derive(Serialize)]
     struct Response<'a> {
-        results: Vec<Result<'a>>,
+        total: usize,
+        results: Vec<SearchResult<'a>>,
     }
 
     #[derive(Serialize)]
-    struct Result<'a> {
-        name: &'a str,
+    struct SearchResult<'a> {
         #[serde(flatten)]
-        sid: SearchId<'a>,
-        rarity: Option<Rarity>,
+        shim: EntityShim<'a>,
+        score: i32,
+        typos: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        snippet: Option<Vec<Fragment>>,
     }
 
     let db = &*db;
+    let matches = db.search_text(&query.q);
 
-    let response = Response {
-        results: db
-            .search
-            .find(&query)
+    json(&Response {
+        total: matches.len(),
+        results: matches
             .into_iter()
-            .take(10)
-            .map(|(eid, _)| {
-                let (sid, name, rarity) = match eid {
-                    EntityId::Champion(id) => {
-                        let rune = db.champs.get(id).unwrap();
-
-                        (
-                            SearchId::Champion(id),
-                            &rune.core.raw.name,
-                            Some(rune.core.raw.rarity),
-                        )
-                    }
-                    EntityId::Spell(id) => {
-                        let rune = db.spells.get(id).unwrap();
-
-                        (
-                            SearchId::Spell(id),
-                            &rune.core.raw.name,
-                            Some(rune.core.raw.rarity),
-                        )
-                    }
-                    EntityId::Equip(id) => {
-                        let rune = db.equips.get(id).unwrap();
-
-                        (
-                            SearchId::Equip(id),
-                            &rune.core.raw.name,
-                            Some(rune.core.raw.rarity),
-                        )
-                    }
-                    EntityId::Relic(id) => {
-                        let rune = db.relics.get(id).unwrap();
-
-                        (
-                            SearchId::Relic(id),
-                            &rune.core.raw.name,
-                            Some(rune.core.raw.rarity),
-                        )
-                    }
-                    EntityId::AbilityGroup(id) => (
-                        SearchId::AbilityGroup(id),
-                        &db.ability_groups[id].name,
-                        None,
-                    ),
-                    EntityId::Effect(id) => {
-                        let effect = &db.effects[id];
-                        let sid = effect.search_id();
-
-                        (sid, &effect.name, None)
-                    }
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(20).min(100))
+            .filter_map(|(eid, score, text)| {
+                let snippet = if text {
+                    db.document(eid).and_then(|doc| db::snippet(&doc, &query.q))
+                } else {
+                    None
                 };
 
-                Result { name, sid, rarity }
+                Some(SearchResult {
+                    shim: db.shim(eid)?,
+                    score: score.points,
+                    typos: score.typos,
+                    snippet,
+                })
             })
             .collect(),
-    };
+    })
+}
 
-    json(&response)
+#[get("/runes")]
+async fn get_runes(query: Query<RuneQuery>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    json(&db.query(&query))
 }
 
 #[get("/champ/{id}")]
-async fn get_champ(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_champ(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
     let db = &*db;
     let champ = db.champs.get(*id).ok_or(NotFound)?;
 
@@ -218,8 +286,13 @@ async fn get_champ(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
     })
 }
 
+#[get("/compare")]
+async fn get_compare(query: Query<CompareQuery>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    json(&Comparison::new(&db, &query.champs).ok_or(NotFound)?)
+}
+
 #[get("/spell/{id}")]
-async fn get_spell(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_spell(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
     let db = &*db;
     let spell = db.spells.get(*id).ok_or(NotFound)?;
     let artists = [db.artists.get(spell.artist).ok_or(NotFound)?.shim()];
@@ -237,7 +310,7 @@ async fn get_spell(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
 }
 
 #[get("/equip/{id}")]
-async fn get_equip(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_equip(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
     let db = &*db;
     let equip = db.equips.get(*id).ok_or(NotFound)?;
     let artists = [db.artists.get(equip.artist).ok_or(NotFound)?.shim()];
@@ -255,7 +328,7 @@ async fn get_equip(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
 }
 
 #[get("/relic/{id}")]
-async fn get_relic(path: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_relic(path: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
     let db = &*db;
     let id = path.into_inner();
     let relic = db.relics.get(id).ok_or(NotFound)?;
@@ -274,12 +347,13 @@ async fn get_relic(path: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error>
 }
 
 #[get("/ability/{id}")]
-async fn get_ability(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_ability(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
     struct Response<'a> {
         ability_groups: [&'a AbilityGroup; 1],
         abilities: Vec<&'a Ability>,
+        used_by: Vec<EntityShim<'a>>,
     }
 
     let db = &*db;
@@ -295,21 +369,219 @@ async fn get_ability(id: Path<Id>, db: Data<DB>) -> Result<HttpResponse, Error>
     json(&Response {
         ability_groups: [group],
         abilities,
+        used_by: db.shims(&group.tags).collect(),
     })
 }
 
 #[get("/effect/{key}")]
-async fn get_effect(key: Path<String>, db: Data<DB>) -> Result<HttpResponse, Error> {
+async fn get_effect(key: Path<String>, db: Live<DB>) -> Result<HttpResponse, Error> {
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
     struct Response<'a> {
         effects: [&'a Effect; 1],
+        used_by: Vec<EntityShim<'a>>,
     }
 
     let db = &*db;
     let effect = db.effects.get_by_key(&key).ok_or(NotFound)?;
 
-    json(&Response { effects: [effect] })
+    json(&Response {
+        effects: [effect],
+        used_by: db.shims(&effect.tags).collect(),
+    })
+}
+
+#[get("/faction/{id}")]
+async fn get_faction(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Response<'a> {
+        factions: [Shim<'a>; 1],
+        used_by: Vec<EntityShim<'a>>,
+    }
+
+    let db = &*db;
+    let faction = db.factions.get(*id).ok_or(NotFound)?;
+
+    json(&Response {
+        factions: [faction.shim()],
+        used_by: db.shims(&faction.tags).collect(),
+    })
+}
+
+#[get("/artist/{id}")]
+async fn get_artist(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Response<'a> {
+        artists: [Shim<'a>; 1],
+        used_by: Vec<EntityShim<'a>>,
+    }
+
+    let db = &*db;
+    let artist = db.artists.get(*id).ok_or(NotFound)?;
+
+    json(&Response {
+        artists: [artist.shim()],
+        used_by: db.shims(&artist.tags).collect(),
+    })
+}
+
+#[get("/expansion/{id}")]
+async fn get_expansion(id: Path<Id>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Response<'a> {
+        expansions: [Shim<'a>; 1],
+        used_by: Vec<EntityShim<'a>>,
+    }
+
+    let db = &*db;
+    let expansion = db.expansions.get(*id).ok_or(NotFound)?;
+
+    json(&Response {
+        expansions: [expansion.shim()],
+        used_by: db.shims(&expansion.tags).collect(),
+    })
+}
+
+#[post("/deck/validate")]
+async fn post_deck_validate(deck: Json<Deck>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    let mut deck = deck.into_inner();
+
+    deck.normalize(&db);
+
+    json(&deck.validate(&db))
+}
+
+#[get("/deck/{code}")]
+async fn get_deck(code: Path<String>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    let mut deck: Deck = code.parse()?;
+
+    deck.normalize(&db);
+
+    json(&deck.validate(&db))
+}
+
+/// Most changelogs returned by `/changes` at once.
+const MAX_CHANGES: usize = 50;
+
+#[derive(Deserialize)]
+struct Page {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[get("/changes")]
+async fn get_changes(page: Query<Page>, history: Live<History>) -> Result<HttpResponse, Error> {
+    #[derive(Serialize)]
+    struct Response<'a> {
+        total: usize,
+        versions: Vec<&'a history::Changelog>,
+    }
+
+    json(&Response {
+        total: history.versions.len(),
+        versions: history
+            .versions
+            .iter()
+            .rev()
+            .skip(page.offset)
+            .take(page.limit.unwrap_or(10).min(MAX_CHANGES))
+            .collect(),
+    })
+}
+
+#[get("/champ/{id}/cost")]
+async fn get_champ_cost(id: Path<Id>, query: Query<CostQuery>, db: Live<DB>) -> Result<HttpResponse, Error> {
+    let db = &*db;
+    let champ = db.champs.get(*id).ok_or(NotFound)?;
+    let picks = champ.upgrades(&query.upgrades.0)?;
+
+    json(&CostBreakdown::new(db, champ, picks).ok_or(NotFound)?)
+}
+
+#[get("/champ/{id}/history")]
+async fn get_champ_history(
+    id: Path<Id>,
+    db: Live<DB>,
+    history: Live<History>,
+) -> Result<HttpResponse, Error> {
+    #[derive(Serialize)]
+    struct Response<'a> {
+        versions: Vec<Version<'a>>,
+    }
+
+    #[derive(Serialize)]
+    struct Version<'a> {
+        version: u32,
+        timestamp: u64,
+        changes: Vec<&'a Change>,
+    }
+
+    let id = *id;
+    let versions = history
+        .versions
+        .iter()
+        .rev()
+        .filter_map(|log| {
+            let changes = log
+                .changes
+                .iter()
+                .filter(|change| change.champion() == Some(id))
+                .collect::<Vec<_>>();
+
+            if changes.is_empty() {
+                return None;
+            }
+
+            Some(Version {
+                version: log.version,
+                timestamp: log.timestamp,
+                changes,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if versions.is_empty() && db.champs.get(id).is_none() {
+        return Err(NotFound.into());
+    }
+
+    json(&Response { versions })
+}
+
+#[get("/assets/{category}/{file}")]
+async fn get_asset(
+    req: HttpRequest,
+    path: Path<(String, String)>,
+    query: Query<VariantQuery>,
+    dir: Data<AssetDir>,
+    variants: Data<Variants>,
+) -> Result<HttpResponse, Error> {
+    let (category, file) = &*path;
+
+    if category != "runes" || query.is_original() {
+        return assets::serve(&dir, &req, category, file).await;
+    }
+
+    let hash = file
+        .strip_suffix(".jpg")
+        .filter(|hash| assets::is_plain_name(hash))
+        .ok_or(NotFound)?
+        .to_owned();
+    let format = query.format.unwrap_or(Format::Jpg);
+
+    if !format.is_supported() {
+        return Err(UnsupportedFormat.into());
+    }
+
+    let variants = variants.into_inner();
+    let path = web::block(move || variants.get(&hash, &query).ok_or(NotFound))
+        .await
+        .map_err(|_| NotFound)?;
+
+    assets::serve_file(&req, path, format.content_type(), assets::CACHE_IMMUTABLE).await
 }
 
 struct BackgroundServer {
@@ -327,9 +599,13 @@ impl BackgroundServer {
     }
 }
 
-fn spawn_server(db: DB, socket: SocketAddr) -> BackgroundServer {
-    let db = Data::new(db);
-
+fn spawn_server(
+    db: Handle<DB>,
+    history: Handle<History>,
+    assets: Data<AssetDir>,
+    variants: Data<Variants>,
+    socket: SocketAddr,
+) -> BackgroundServer {
     let (tx, rx) = mpsc::channel();
 
     thread::spawn(move || -> std::io::Result<()> {
@@ -340,15 +616,30 @@ fn spawn_server(db: DB, socket: SocketAddr) -> BackgroundServer {
         let server = HttpServer::new(move || {
             App::new()
                 .wrap(middleware::DefaultHeaders::new().header("Access-Control-Allow-Origin", "*"))
-                .app_data(db.clone()) // Data<DB> is internally an Arc, so all this does is increment RC
+                .app_data(db.clone()) // Handle<DB> is internally an Arc, so all this does is increment RC
+                .app_data(history.clone())
+                .app_data(assets.clone())
+                .app_data(variants.clone())
                 .service(get_init)
                 .service(get_typeahead)
+                .service(get_search)
+                .service(get_runes)
                 .service(get_champ)
+                .service(get_champ_cost)
+                .service(get_compare)
+                .service(get_champ_history)
+                .service(get_changes)
                 .service(get_spell)
                 .service(get_equip)
                 .service(get_relic)
                 .service(get_ability)
                 .service(get_effect)
+                .service(get_faction)
+                .service(get_artist)
+                .service(get_expansion)
+                .service(post_deck_validate)
+                .service(get_deck)
+                .service(get_asset)
         })
         .bind(socket)?
         .shutdown_timeout(1)
@@ -369,6 +660,14 @@ fn spawn_server(db: DB, socket: SocketAddr) -> BackgroundServer {
     rx.recv().unwrap()
 }
 
+fn prune_assets(dir: &std::path::Path, db: &DB) {
+    match manifest::prune(dir, db) {
+        Ok((0, _)) => (),
+        Ok((files, bytes)) => log::info!("🗑️  Pruned {} unused files, {} KiB", files, bytes / 1024),
+        Err(err) => log::error!("❌ Failed pruning assets: {}", err),
+    }
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
     SimpleLogger::new()
@@ -377,12 +676,84 @@ async fn main() -> anyhow::Result<()> {
         .expect("Must be able to start a logger");
 
     let opts: Opts = Opts::parse();
+
+    if let Some(Command::DecodePep { files, out }) = &opts.command {
+        return poxbase::image::run(files, out.as_deref());
+    }
+
     let interval = Duration::from_secs(3600 * 6);
+    let downloader = assets::Downloader::new(
+        &opts.cdn,
+        opts.assets.clone(),
+        opts.download_concurrency,
+        opts.download_retries,
+    );
+    let variants = Data::new(Variants::new(opts.assets.clone(), opts.thumbnail_sizes.clone()));
+
+    if opts.assets_only {
+        let (mut db, _) = parser::parse(&opts.feed, &DB::default(), None)
+            .await?
+            .expect("Feed is always parsed without a previous hash");
+
+        assets::check(&mut db, &downloader, &variants).await;
+
+        if opts.prune {
+            prune_assets(&opts.assets, &db);
+        }
+
+        return Ok(());
+    }
+
+    let ranking = match &opts.ranking {
+        Some(path) => Ranking::load(path)?,
+        None => Ranking::default(),
+    };
+    let popularity = match &opts.popularity {
+        Some(path) => Popularity::load(path)?,
+        None => Popularity::default(),
+    };
+
+    if opts.popularity.is_some() {
+        log::info!("📈 Loaded popularity of {} entities", popularity.len());
+    }
 
-    let mut prev_hash = None;
+    let mut prev_hash: Option<Hash> = None;
     let mut server: Option<BackgroundServer> = None;
     let mut interval = time::interval(interval);
 
+    let mut history = if opts.no_history {
+        History::default()
+    } else {
+        History::load(&opts.history).with_context(|| {
+            format!(
+                "Couldn't load history from {}, move the changelog aside or run with --no-history",
+                opts.history.display(),
+            )
+        })?
+    };
+
+    // The server is only started once, after that new versions of the feed are swapped in place
+    let db = live::handle(DB::default());
+    let history_handle = live::handle(history.clone());
+    let asset_dir = Data::new(AssetDir(opts.assets.clone()));
+
+    if !opts.no_snapshot {
+        match snapshot::load(&opts.snapshot) {
+            Ok((mut snapshot, hash)) => {
+                log::info!("💾 Loaded snapshot from {}", opts.snapshot.display());
+
+                snapshot.rank(&ranking, &popularity);
+
+                db.store(Arc::new(snapshot));
+                prev_hash = Some(hash);
+                server = Some(spawn_server(db.clone(), history_handle.clone(), asset_dir.clone(), variants.clone(), opts.socket));
+            }
+            Err(err) => {
+                log::warn!("⚠️  Couldn't load snapshot from {}: {}", opts.snapshot.display(), err);
+            }
+        }
+    }
+
     loop {
         let sigint = actix_web::rt::signal::ctrl_c();
         let tick = interval.tick();
@@ -397,28 +768,53 @@ async fn main() -> anyhow::Result<()> {
             break;
         }
 
-        match parser::parse(prev_hash).await {
-            Ok(Some((mut db, hash))) => {
-                prev_hash = Some(hash);
-
-                parser::create_search_index(&mut db);
+        match parser::parse(&opts.feed, &db.load_full(), prev_hash).await {
+            Ok(Some((mut new, hash))) => {
+                parser::create_search_index(&mut new);
+                new.rank(&ranking, &popularity);
 
                 if !opts.no_assets {
-                    assets::check(&mut db).await;
+                    assets::check(&mut new, &downloader, &variants).await;
+
+                    if opts.prune {
+                        prune_assets(&opts.assets, &new);
+                    }
+                }
+
+                if !opts.no_snapshot {
+                    match snapshot::save(&new, &hash, &opts.snapshot) {
+                        Ok(()) => log::info!("💾 Saved snapshot to {}", opts.snapshot.display()),
+                        Err(err) => log::error!("❌ Failed saving snapshot: {}", err),
+                    }
                 }
 
-                if let Some(server) = server.take() {
-                    server.stop().await;
+                if let (Some(old_hash), false) = (&prev_hash, opts.no_history) {
+                    match history.record(&opts.history, opts.history_snapshots, &db.load(), old_hash, &new, &hash) {
+                        Ok(log) => log::info!("📰 Recorded version {} with {} changes", log.version, log.changes.len()),
+                        Err(err) => log::error!("❌ Failed recording history: {}", err),
+                    }
+
+                    history_handle.store(Arc::new(history.clone()));
                 }
 
-                server = Some(spawn_server(db, opts.socket));
+                db.store(Arc::new(new));
+                prev_hash = Some(hash);
+
+                match server {
+                    Some(_) => log::info!("🔁 Swapped in new DB"),
+                    None => server = Some(spawn_server(db.clone(), history_handle.clone(), asset_dir.clone(), variants.clone(), opts.socket)),
+                }
             }
             Ok(None) => (),
             Err(err) => {
-                log::error!("❌ Failed parsing: {}", err);
+                log::error!("❌ Failed parsing {}: {}", opts.feed, err);
             }
         }
     }
 
+    if let Some(server) = server {
+        server.stop().await;
+    }
+
     Ok(())
 }
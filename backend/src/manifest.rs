@@ -0,0 +1,278 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Record of the art mirrored into the assets directory, and removal of art that
+//! is no longer used by anything in the feed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rustc_hash::FxHashSet;
+use serde::{Serialize, Deserialize};
+use tiny_keccak::{Keccak, Hasher};
+
+use crate::assets;
+use crate::db::DB;
+use crate::util::{self, hex};
+
+const MANIFEST: &str = "manifest.json";
+
+/// Directories holding art for runes, named by hash, with the extension of files in them.
+const RUNE_DIRS: &[(&str, &str)] = &[("runes", "jpg"), ("minis", "png"), ("idols", "gif")];
+
+/// Directories holding ability icons, named `icon_{name}`, with the extension of files in them.
+const ICON_DIRS: &[(&str, &str)] = &[("big_icons", "png"), ("small_icons", "gif")];
+
+/// Directory holding generated variants of rune art, see `variants`.
+const VARIANTS_DIR: (&str, &[&str]) = ("variants", &["jpg", "webp"]);
+
+/// Age after which a temporary `.part` file is assumed to be left over from an
+/// interrupted write, rather than one still in progress.
+const STALE_PART: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub size: u64,
+    /// Hex encoded Keccak-256 of the file
+    pub checksum: String,
+    /// Unix timestamp of when the file was written
+    pub fetched: u64,
+}
+
+/// Files present for a single rune hash or icon name, by directory.
+pub type Files = BTreeMap<Box<str>, FileInfo>;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Manifest {
+    pub updated: u64,
+    pub runes: BTreeMap<Box<str>, Files>,
+    pub icons: BTreeMap<Box<str>, Files>,
+    /// Icons the CDN only has a small version of, so syncs don't keep asking for the big one.
+    #[serde(default)]
+    pub no_big_icon: BTreeSet<Box<str>>,
+}
+
+fn checksum(bytes: &[u8]) -> String {
+    let mut hasher = Keccak::v256();
+    let mut hash = [0u8; 32];
+
+    hasher.update(bytes);
+    hasher.finalize(&mut hash);
+
+    hex(&hash)
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        match fs::File::open(dir.join(MANIFEST)) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        util::write_atomic(&dir.join(MANIFEST), |file| {
+            serde_json::to_writer(file, self).map_err(io::Error::from)
+        })?;
+
+        Ok(())
+    }
+
+    /// Build a manifest of all art in `dir` used by the `db`. Checksums are only
+    /// computed for files that have changed since the `previous` manifest.
+    pub fn build(dir: &Path, db: &DB, previous: &Manifest) -> anyhow::Result<Self> {
+        let mut manifest = Manifest {
+            updated: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            ..Manifest::default()
+        };
+
+        for (_, raw, _) in assets::runes(db) {
+            let hash = raw.hash.as_str();
+            let files = collect(dir, RUNE_DIRS, hash, previous.runes.get(hash))?;
+
+            if !files.is_empty() {
+                manifest.runes.insert(hash.into(), files);
+            }
+        }
+
+        for ability in db.abilities.iter() {
+            let name = &ability.core.icon_name;
+
+            if manifest.icons.contains_key(name) {
+                continue;
+            }
+
+            let stem = format!("icon_{}", name);
+            let files = collect(dir, ICON_DIRS, &stem, previous.icons.get(name))?;
+
+            if !files.is_empty() {
+                manifest.icons.insert(name.clone(), files);
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+fn collect(dir: &Path, dirs: &[(&str, &str)], stem: &str, previous: Option<&Files>) -> io::Result<Files> {
+    let mut files = Files::new();
+
+    for &(category, ext) in dirs {
+        let path = dir.join(category).join(format!("{}.{}", stem, ext));
+        let meta = match fs::metadata(&path) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        let size = meta.len();
+        let fetched = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let known = previous
+            .and_then(|files| files.get(category))
+            .filter(|info| info.size == size && info.fetched == fetched);
+
+        let info = match known {
+            Some(info) => info.clone(),
+            None => FileInfo {
+                size,
+                checksum: checksum(&fs::read(&path)?),
+                fetched,
+            },
+        };
+
+        files.insert(category.into(), info);
+    }
+
+    Ok(files)
+}
+
+/// Delete art, generated variants and leftover partial downloads that aren't used
+/// by any rune or ability in the `db`. Returns the number of files and bytes removed.
+pub fn prune(dir: &Path, db: &DB) -> io::Result<(usize, u64)> {
+    let hashes = assets::runes(db)
+        .map(|(_, raw, _)| raw.hash.as_str())
+        .collect::<FxHashSet<_>>();
+    let icons = db
+        .abilities
+        .iter()
+        .map(|ability| format!("icon_{}", ability.core.icon_name))
+        .collect::<FxHashSet<_>>();
+
+    let mut removed = (0, 0);
+
+    // An empty feed would mean wiping every single file, that's never what we want
+    if hashes.is_empty() {
+        log::warn!("⚠️  No runes in the feed, not pruning assets");
+        return Ok(removed);
+    }
+
+    for &(category, ext) in RUNE_DIRS {
+        prune_dir(&dir.join(category), &[ext], |stem| hashes.contains(stem), &mut removed)?;
+    }
+    for &(category, ext) in ICON_DIRS {
+        prune_dir(&dir.join(category), &[ext], |stem| icons.contains(stem), &mut removed)?;
+    }
+
+    let (category, exts) = VARIANTS_DIR;
+
+    // Variants are named `{hash}.{ext}` or `{hash}-{width}.{ext}`
+    prune_dir(
+        &dir.join(category),
+        exts,
+        |stem| hashes.contains(stem.split('-').next().unwrap_or(stem)),
+        &mut removed,
+    )?;
+
+    Ok(removed)
+}
+
+fn prune_dir(
+    dir: &Path,
+    exts: &[&str],
+    used: impl Fn(&str) -> bool,
+    removed: &mut (usize, u64),
+) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+
+        let stem = path.file_stem().and_then(|stem| stem.to_str());
+        let ext = path.extension().and_then(|ext| ext.to_str());
+
+        let (stem, ext) = match (stem, ext) {
+            (Some(stem), Some(ext)) => (stem, ext),
+            _ => continue,
+        };
+
+        // Never touch files we didn't put there ourselves, or temporary files still being written
+        let orphaned = match ext {
+            "part" => is_stale(&entry.metadata()?),
+            ext if exts.contains(&ext) => !used(stem),
+            _ => false,
+        };
+
+        if orphaned && entry.file_type()?.is_file() {
+            let size = entry.metadata()?.len();
+
+            fs::remove_file(&path)?;
+            log::info!("🗑️  Removed {}", path.display());
+
+            removed.0 += 1;
+            removed.1 += size;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_stale(metadata: &fs::Metadata) -> bool {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > STALE_PART)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test::TempDir;
+
+    #[test]
+    fn prune_keeps_fresh_parts() {
+        let dir = TempDir::with_files(&["used.jpg", "orphan.jpg", "download.part", "notes.txt"]);
+        let mut removed = (0, 0);
+
+        prune_dir(dir.path(), &["jpg"], |stem| stem == "used", &mut removed).unwrap();
+
+        assert_eq!(removed, (1, 4));
+        assert_eq!(dir.files(), ["download.part", "notes.txt", "used.jpg"]);
+    }
+}
@@ -14,21 +14,82 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::{fs, io};
-use std::fmt::Write;
+use std::fs;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Instant;
+use actix_web::error::BlockingError;
+use actix_web::web;
 use arrayvec::ArrayVec;
 use serde::Deserialize;
 use regex::Regex;
 use tiny_keccak::{Keccak, Hasher};
 
-use crate::db::{DB, TableItem, AutoIndexItem};
+use crate::db::{DB, SearchIndex, TableItem, AutoIndexItem};
 use crate::types::{Id, EntityId, Faction, AbilityCore, ChampionCore};
 use crate::types::{Spell, Equip, Relic, Effect, EffectKind};
 // https://www.poxnora.com/api/feed.do?t=json
 // https://www.poxnora.com/api/feed.do?t=json&r=mechanics
 // https://www.poxnora.com/api/feed.do?t=json&r=conditions
 
+pub const FEED_URL: &str = "https://www.poxnora.com/api/feed.do?t=json";
+
+/// Where to load the feed from, either a remote URL or a local JSON file.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Url(Box<str>),
+    File(PathBuf),
+}
+
+impl FromStr for Source {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(Source::Url(s.into()))
+        } else {
+            Ok(Source::File(s.into()))
+        }
+    }
+}
+
+impl Source {
+    /// Location of an auxiliary feed resource, such as `mechanics` or `conditions`.
+    ///
+    /// For remote feeds this is the `r` query parameter, for local files this is a sibling
+    /// file with the resource name appended to the file stem, e.g. `feed-mechanics.json`.
+    pub fn resource(&self, resource: &str) -> Source {
+        match self {
+            Source::Url(url) => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+
+                Source::Url(format!("{}{}r={}", url, separator, resource).into())
+            }
+            Source::File(path) => {
+                let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("feed");
+                let mut name = format!("{}-{}", stem, resource);
+
+                if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
+                    name.push('.');
+                    name.push_str(ext);
+                }
+
+                Source::File(path.with_file_name(name))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Url(url) => f.write_str(url),
+            Source::File(path) => path.display().fmt(f),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct Feed {
     champs: Vec<FeedChamp>,
@@ -75,19 +136,36 @@ struct AbilitySet {
     abilities: ArrayVec<[FeedAbility; 4]>,
 }
 
-type Hash = [u8; 32];
+#[derive(Deserialize, Debug)]
+struct FeedEffect {
+    key: Box<str>,
+    name: Box<str>,
+    #[serde(alias = "desc")]
+    description: Box<str>,
+}
+
+pub type Hash = [u8; 32];
 
-pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>> {
-    let start = Instant::now();
-    // let feed = fs::File::open("./feed.json")?;
-    // let reader = io::BufReader::new(feed);
-    // let mut feed: Feed = serde_json::from_reader(reader)?;
+/// Load and parse the feed, unless its hash is the same as the `previous` one. The
+/// `current` DB is kept while any of the auxiliary feeds it was built with is missing.
+pub async fn parse(source: &Source, current: &DB, previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>> {
+    let feed = Parser::load(source).await?;
+    let mechanics = Parser::load_optional(&source.resource("mechanics")).await;
+    let conditions = Parser::load_optional(&source.resource("conditions")).await;
 
-    let mut hasher = Keccak::v256();
-    // let feed = Parser::get("http://localhost:1337/feed.json", &mut hasher).await?;
-    let feed = Parser::get("https://www.poxnora.com/api/feed.do?t=json", &mut hasher).await?;
+    for (bytes, kind) in [(&mechanics, EffectKind::Mechanic), (&conditions, EffectKind::Condition)].iter() {
+        if bytes.is_none() && current.effects.iter().any(|effect| effect.kind == *kind) {
+            log::warn!("⚠️  Keeping the current DB until {:?} effects are available again", kind);
+            return Ok(None);
+        }
+    }
 
+    let mut hasher = Keccak::v256();
     let mut hash = [0u8; 32];
+
+    for bytes in Some(&feed).into_iter().chain(&mechanics).chain(&conditions) {
+        hasher.update(bytes);
+    }
     hasher.finalize(&mut hash);
 
     if let Some(previous) = previous {
@@ -99,7 +177,25 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
         }
     }
 
-    let mut feed: Feed = serde_json::from_slice(&feed)?;
+    let db = build(&feed, mechanics.as_deref(), conditions.as_deref())?;
+
+    Ok(Some((db, hash)))
+}
+
+/// Build the DB from raw feed payloads. Missing mechanics or conditions only
+/// mean effect tags in descriptions can't be linked and render as plain text.
+pub fn build(feed: &[u8], mechanics: Option<&[u8]>, conditions: Option<&[u8]>) -> anyhow::Result<DB> {
+    let start = Instant::now();
+
+    let mut feed: Feed = serde_json::from_slice(feed)?;
+    let mechanics: Vec<FeedEffect> = match mechanics {
+        Some(bytes) => serde_json::from_slice(bytes)?,
+        None => Vec::new(),
+    };
+    let conditions: Vec<FeedEffect> = match conditions {
+        Some(bytes) => serde_json::from_slice(bytes)?,
+        None => Vec::new(),
+    };
 
     log::info!("📜 Parsed feed.json in {:?}", start.elapsed());
 
@@ -121,6 +217,22 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
         entry.kind = EffectKind::Damage;
     }
 
+    let effects = mechanics.into_iter().map(|effect| (effect, EffectKind::Mechanic))
+        .chain(conditions.into_iter().map(|effect| (effect, EffectKind::Condition)));
+
+    for (feffect, kind) in effects {
+        let entry = db.effects.entry(&feffect.key);
+
+        if entry.kind != EffectKind::None {
+            log::warn!("⚠️  Duplicate effect <{}> ({:?} and {:?})", feffect.key, entry.kind, kind);
+            continue;
+        }
+
+        entry.name = feffect.name;
+        entry.desc = feffect.description;
+        entry.kind = kind;
+    }
+
     for fchamp in feed.champs {
         let champ = db.champs.entry(fchamp.core);
 
@@ -128,7 +240,7 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
             let ability = db.abilities.entry(core);
 
             ability.group = db.ability_groups
-                .entry(&ability.core.name)
+                .entry(ability.core.name.trim())
                 .rank(ability)
                 .tag(&*champ)
                 .id();
@@ -140,7 +252,7 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
             let ability = db.abilities.entry(fability.core);
 
             ability.group = db.ability_groups
-                .entry(&ability.core.name)
+                .entry(ability.core.name.trim())
                 .rank(ability)
                 .tag(&*champ)
                 .id();
@@ -154,7 +266,7 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
             let ability = db.abilities.entry(fability.core);
 
             ability.group = db.ability_groups
-                .entry(&ability.core.name)
+                .entry(ability.core.name.trim())
                 .rank(ability)
                 .tag(&*champ)
                 .id();
@@ -182,6 +294,26 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
         champ.artist = db.artists.entry(&fchamp.artist).tag(&*champ).id();
     }
 
+    let mut conflicts = 0;
+    let mut duplicates = 0;
+
+    for group in db.ability_groups.iter_mut() {
+        duplicates += group.order_ranks(&mut db.abilities);
+
+        for [a, b] in &group.conflicts {
+            log::warn!("⚠️  Conflicting ranks of <{}>, abilities {} and {} have the same level", group.name, a, b);
+        }
+
+        conflicts += group.conflicts.len();
+    }
+
+    if conflicts > 0 {
+        log::warn!("⚠️  Found {} conflicting ability ranks", conflicts);
+    }
+    if duplicates > 0 {
+        log::info!("♊ Found {} abilities identical to another rank of the same level", duplicates);
+    }
+
     for fspell in feed.spells {
         let spell = db.spells.entry(fspell.core);
 
@@ -288,11 +420,30 @@ pub async fn parse(previous: Option<Hash>) -> anyhow::Result<Option<(DB, Hash)>>
         slot += 1;
     }
 
+    let mut id = 0;
+
+    while let Some(effect) = db.effects.get(id) {
+        let name = &effect.name;
+        let desc = &effect.desc;
+        let tag_id = EntityId::Effect(id);
+
+        // Damage types have a generated description that would only link to itself
+        if effect.kind != EffectKind::Damage {
+            if let Some(fixed) = parser.fix_desc(&db, name, desc) {
+                db.effects[id].desc = fixed.into();
+
+                parser.tag(&mut db, tag_id);
+            }
+        }
+
+        id += 1;
+    }
+
     log::info!("⚔️  Cross-references abilities and conditions in {:?}", start.elapsed());
 
     // println!("{:#?}", db.effects);
 
-    Ok(Some((db, hash)))
+    Ok(db)
 }
 
 struct Parser {
@@ -324,25 +475,57 @@ impl Parser {
         }
     }
 
-    async fn get<H>(url: &str, hasher: &mut H) -> anyhow::Result<Vec<u8>>
-    where
-        H: Hasher,
-    {
+    async fn load(source: &Source) -> anyhow::Result<Vec<u8>> {
+        match source {
+            Source::Url(url) => Self::get(url).await,
+            Source::File(path) => Self::read(path).await,
+        }
+    }
+
+    /// Like `load`, but an auxiliary feed of effects that can't be loaded or parsed
+    /// is only a warning.
+    async fn load_optional(source: &Source) -> Option<Vec<u8>> {
+        let loaded = Self::load(source).await.and_then(|bytes| {
+            serde_json::from_slice::<Vec<FeedEffect>>(&bytes)?;
+
+            Ok(bytes)
+        });
+
+        match loaded {
+            Ok(bytes) => Some(bytes),
+            Err(err) => {
+                log::warn!("⚠️  Couldn't load {}, effects won't be linked: {}", source, err);
+                None
+            }
+        }
+    }
+
+    async fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+        log::info!("📂 Reading {}...", path.display());
+
+        // Feeds are large, read them on the thread pool instead of blocking the runtime
+        let owned = path.to_owned();
+        let bytes = web::block(move || fs::read(owned)).await.map_err(|err| match err {
+            BlockingError::Error(err) => anyhow::Error::from(err),
+            BlockingError::Canceled => anyhow::anyhow!("Thread pool is gone"),
+        })?;
+
+        Ok(bytes)
+    }
+
+    async fn get(url: &str) -> anyhow::Result<Vec<u8>> {
         use futures_util::StreamExt;
 
-        log::info!("⬇️  Downloading and hashing {}...", url);
+        log::info!("⬇️  Downloading {}...", url);
 
-        let request = reqwest::get(url).await?;
+        let request = reqwest::get(url).await?.error_for_status()?;
         let capacity = request.content_length().unwrap_or(1024) as usize;
 
         let mut stream = request.bytes_stream();
         let mut bytes = Vec::with_capacity(capacity);
 
         while let Some(item) = stream.next().await {
-            let chunk = item?;
-
-            hasher.update(&chunk);
-            bytes.extend_from_slice(&chunk);
+            bytes.extend_from_slice(&item?);
         }
 
         Ok(bytes)
@@ -381,13 +564,17 @@ impl Parser {
             }
 
             let value = match self.value_re.captures(&caps[2]) {
-                Some(c) => c.get(1).unwrap().as_str(),
+                Some(c) => c.get(1).unwrap().as_str().trim_matches('"'),
                 None => {
                     log::warn!("⚠️  Missing value for <{}> inside <{}>", text, name);
                     ""
                 }
             };
-            let (id, tag) = match tag {
+            let m = caps.get(0).unwrap();
+            self.buffer.push_str(&desc[last..m.start()]);
+            last = m.end();
+
+            match tag {
                 "ability" => {
                     let aid: Id = match value.parse() {
                         Ok(id) => id,
@@ -396,37 +583,40 @@ impl Parser {
                             !0
                         }
                     };
-                    let gid = match db.abilities.get(aid) {
+                    match db.abilities.get(aid) {
                         Some(ability) => {
                             self.tags.push(TagId::AbilityGroup(ability.group));
 
-                            ability.group
+                            write!(&mut self.buffer, "[{}](/ability/{})", text, ability.group).unwrap();
                         }
                         None => {
                             log::warn!("⚠️  Unknown ability <{}> {} inside <{}>", text, aid, name);
-                            !0
+                            write!(&mut self.buffer, "[{}](*)", text).unwrap();
                         }
-                    };
-
-                    (gid, "ability")
+                    }
                 },
-                "condition" => (!0, "effect"),
-                "mechanic" => (!0, "effect"),
-                _ => unreachable!(),
-            };
+                "condition" | "mechanic" => {
+                    // Keys in the feed aren't always consistent with tag values, fall back to the displayed name
+                    let effect = db.effects.get_by_key(&Effect::make_key(value))
+                        .or_else(|| db.effects.get_by_key(&Effect::make_key(text)));
 
-            let m = caps.get(0).unwrap();
-            self.buffer.push_str(&desc[last..m.start()]);
-            last = m.end();
+                    match effect {
+                        Some(effect) => {
+                            self.tags.push(TagId::Effect(effect.id()));
 
-            if id != !0 {
-                write!(&mut self.buffer, "[{}](/{}/{})", text, tag, id).unwrap();
-            } else {
-                write!(&mut self.buffer, "[{}](*)", text).unwrap();
+                            write!(&mut self.buffer, "[{}](/effect/{})", text, &effect.key).unwrap();
+                        }
+                        None => {
+                            log::warn!("⚠️  Unknown {} <{}> {} inside <{}>", tag, text, value, name);
+                            write!(&mut self.buffer, "[{}](*)", text).unwrap();
+                        }
+                    }
+                },
+                _ => unreachable!(),
             }
         }
 
-        if self.buffer.len() != 0 {
+        if !self.buffer.is_empty() {
             self.buffer.push_str(&desc[last..]);
 
             Some(&self.buffer)
@@ -451,26 +641,138 @@ impl Parser {
 
 pub fn create_search_index(db: &mut DB) {
     let start = Instant::now();
+    let mut search = SearchIndex::builder();
 
     for champ in db.champs.iter() {
-        db.search.insert(&champ.core.raw.name, EntityId::Champion(champ.id()));
+        search.insert(&champ.core.raw.name, EntityId::Champion(champ.id()));
     }
     for spell in db.spells.iter() {
-        db.search.insert(&spell.core.raw.name, EntityId::Spell(spell.id()));
+        search.insert(&spell.core.raw.name, EntityId::Spell(spell.id()));
     }
     for equip in db.equips.iter() {
-        db.search.insert(&equip.core.raw.name, EntityId::Equip(equip.id()));
+        search.insert(&equip.core.raw.name, EntityId::Equip(equip.id()));
     }
     for relic in db.relics.iter() {
-        db.search.insert(&relic.core.raw.name, EntityId::Relic(relic.id()));
+        search.insert(&relic.core.raw.name, EntityId::Relic(relic.id()));
     }
     for ability in db.ability_groups.iter() {
-        db.search.insert(&ability.name, EntityId::AbilityGroup(ability.id()));
+        search.insert(&ability.name, EntityId::AbilityGroup(ability.id()));
     }
     for effect in db.effects.iter() {
-        db.search.insert(&effect.name, EntityId::Effect(effect.id));
+        search.insert(&effect.name, EntityId::Effect(effect.id));
     }
 
-    log::info!("🔎 Created search index in {:?} ({}kb)", start.elapsed(), db.search.size() / 1024);
+    db.search = search.build();
+
+    db.create_text_index();
+
+    log::info!(
+        "🔎 Created search index in {:?} ({}kb names, {}kb text)",
+        start.elapsed(),
+        db.search.size() / 1024,
+        db.text.size() / 1024,
+    );
     // println!("{:#?}", db.search);
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use actix_web::rt::System;
+    use crate::util::test::TempDir;
+
+    const FEED: &[u8] = include_bytes!("../fixtures/feed.json");
+    const MECHANICS: &[u8] = include_bytes!("../fixtures/feed-mechanics.json");
+    const CONDITIONS: &[u8] = include_bytes!("../fixtures/feed-conditions.json");
+
+    /// Small DB built from the feed fixtures, shared with tests in other modules.
+    pub fn fixture() -> DB {
+        fixture_with(|_| ())
+    }
+
+    /// Like `fixture`, with the feed JSON changed by `edit` before it's parsed.
+    pub fn fixture_with(edit: impl FnOnce(&mut serde_json::Value)) -> DB {
+        let mut feed: serde_json::Value = serde_json::from_slice(FEED).unwrap();
+
+        edit(&mut feed);
+
+        build(&serde_json::to_vec(&feed).unwrap(), Some(MECHANICS), Some(CONDITIONS)).unwrap()
+    }
+
+    #[test]
+    fn feed_effects() {
+        let mechanics: Vec<FeedEffect> = serde_json::from_slice(MECHANICS).unwrap();
+        let conditions: Vec<FeedEffect> = serde_json::from_slice(CONDITIONS).unwrap();
+
+        assert_eq!(mechanics.len(), 2);
+        assert_eq!(&*mechanics[0].key, "flanking");
+        assert_eq!(&*mechanics[0].name, "Flanking");
+        assert_eq!(&*conditions[0].key, "burning");
+        assert_eq!(&*conditions[0].description, "Takes Fire damage at the start of each turn.");
+
+        let db = fixture();
+        let burning = db.effects.get_by_key("burning").unwrap();
+
+        assert_eq!(burning.kind, EffectKind::Condition);
+        assert_eq!(db.effects.get_by_key("flanking").unwrap().kind, EffectKind::Mechanic);
+        assert_eq!(
+            &*db.abilities.get(101).unwrap().core.short_description,
+            "Gains [Burning](/effect/burning) and deals [Fire](/effect/fire) damage.",
+        );
+    }
+
+    #[test]
+    fn effects_are_optional() {
+        let db = build(FEED, None, None).unwrap();
+
+        assert!(db.effects.get_by_key("burning").is_none());
+        assert_eq!(db.effects.get_by_key("fire").unwrap().kind, EffectKind::Damage);
+        assert_eq!(
+            &*db.abilities.get(101).unwrap().core.short_description,
+            "Gains [Burning](*) and deals [Fire](/effect/fire) damage.",
+        );
+    }
+
+    #[test]
+    fn resource_location() {
+        let resource = |source: &str| source.parse::<Source>().unwrap().resource("mechanics").to_string();
+
+        assert_eq!(resource(FEED_URL), "https://www.poxnora.com/api/feed.do?t=json&r=mechanics");
+        assert_eq!(resource("http://localhost:1337/feed.json"), "http://localhost:1337/feed.json?r=mechanics");
+        assert_eq!(resource("fixtures/feed.json"), "fixtures/feed-mechanics.json");
+    }
+
+    #[test]
+    fn keep_db_while_effects_are_missing() {
+        let dir = TempDir::with_files(&[]);
+        let source = Source::File(dir.path().join("feed.json"));
+        let conditions = dir.path().join("feed-conditions.json");
+
+        fs::write(dir.path().join("feed.json"), FEED).unwrap();
+        fs::write(dir.path().join("feed-mechanics.json"), MECHANICS).unwrap();
+        fs::write(&conditions, "<html>Service Unavailable</html>").unwrap();
+
+        System::new("test").block_on(async move {
+            // Conditions that never made it into the DB don't hold it back
+            let (db, hash) = parse(&source, &DB::default(), None).await.unwrap().unwrap();
+
+            assert!(db.effects.get_by_key("burning").is_none());
+            assert!(db.effects.get_by_key("flanking").is_some());
+
+            fs::write(&conditions, CONDITIONS).unwrap();
+
+            let (db, new_hash) = parse(&source, &db, Some(hash)).await.unwrap().unwrap();
+
+            assert!(db.effects.get_by_key("burning").is_some());
+            assert_ne!(hash, new_hash);
+
+            fs::write(&conditions, "<html>Service Unavailable</html>").unwrap();
+
+            assert!(parse(&source, &db, Some(new_hash)).await.unwrap().is_none());
+
+            fs::remove_file(&conditions).unwrap();
+
+            assert!(parse(&source, &db, Some(new_hash)).await.unwrap().is_none());
+        });
+    }
+}
@@ -33,11 +33,38 @@ pub struct AbilityCore {
     pub icon_name: Box<str>,
 }
 
-#[derive(Serialize, Debug)]
+impl AbilityCore {
+    /// `activation_type` of passive abilities. Anything else has to be activated
+    /// by spending AP, and gets the active border in the frontend.
+    pub const PASSIVE: u8 = 0;
+
+    pub fn is_activated(&self) -> bool {
+        self.activation_type != Self::PASSIVE
+    }
+}
+
+/// Which of the icons for an ability is available in the assets.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum IconSize {
+    /// `big_icons/icon_{name}.png`
+    Big,
+    /// `small_icons/icon_{name}.gif`
+    Small,
+    /// Neither could be found on the CDN
+    Missing,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Ability {
     #[serde(flatten)]
     pub core: AbilityCore,
     pub group: Id,
+    /// Rank within the group, counting distinct levels starting at 1. Not always
+    /// the same as `level`, which can skip numbers, such as in levels 1, 2 and 4.
+    pub rank: u8,
+    pub icon_size: IconSize,
 }
 
 impl From<AbilityCore> for Ability {
@@ -45,6 +72,8 @@ impl From<AbilityCore> for Ability {
         Ability {
             core,
             group: !0,
+            rank: 0,
+            icon_size: IconSize::Big,
         }
     }
 }
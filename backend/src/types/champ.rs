@@ -19,6 +19,7 @@ use arrayvec::ArrayVec;
 
 use crate::types::{Id, EntityId, RuneRaw, Size, Faction};
 use crate::db::TableItem;
+use crate::error::InvalidUpgrade;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -34,7 +35,7 @@ pub struct ChampionCore {
     pub size: Size,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Champion {
     #[serde(flatten)]
@@ -65,6 +66,50 @@ impl From<ChampionCore> for Champion {
     }
 }
 
+impl Champion {
+    /// Resolve a selection of upgrade abilities into the pick from each of the
+    /// `ability_sets`, in order. Sets without a selected ability fall back to
+    /// `defaults`, and are `None` if they are empty.
+    pub fn upgrades(&self, selection: &[Id]) -> Result<[Option<Id>; 2], InvalidUpgrade> {
+        let mut picks = [None, None];
+
+        for &id in selection {
+            let set = self
+                .ability_sets
+                .iter()
+                .position(|set| set.contains(&id))
+                .ok_or(InvalidUpgrade::NotAnUpgrade(id))?;
+
+            if let Some(other) = picks[set] {
+                return Err(InvalidUpgrade::SameSet(other, id));
+            }
+
+            picks[set] = Some(id);
+        }
+
+        self.picks(picks)
+    }
+
+    /// Validate upgrade picks given for each of the `ability_sets`, in order,
+    /// filling in `defaults` for sets without a pick, or `None` if they are empty.
+    pub fn picks(&self, mut picks: [Option<Id>; 2]) -> Result<[Option<Id>; 2], InvalidUpgrade> {
+        for (set, pick) in picks.iter_mut().enumerate() {
+            let abilities = &self.ability_sets[set];
+
+            match *pick {
+                Some(id) if !abilities.contains(&id) => {
+                    return Err(InvalidUpgrade::WrongSet(id, set as u8));
+                }
+                Some(_) => (),
+                None if abilities.contains(&self.defaults[set]) => *pick = Some(self.defaults[set]),
+                None => (),
+            }
+        }
+
+        Ok(picks)
+    }
+}
+
 impl From<&Champion> for EntityId {
     fn from(taggable: &Champion) -> EntityId {
         EntityId::Champion(taggable.id())
@@ -82,3 +127,36 @@ impl TableItem for ChampionCore {
         self.raw.id
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgrades() {
+        let mut champ: Champion = serde_json::from_str::<ChampionCore>(
+            r#"{ "id": 1, "name": "Draconic Guard", "description": "", "rarity": 0, "noraCost": 41,
+                 "forSale": true, "tradeable": true, "allowRanked": true, "hash": "", "deckLimit": 2,
+                 "maxRng": 1, "minRng": 1, "defense": 1, "speed": 4, "damage": 11, "hitPoints": 41, "size": "1x1" }"#,
+        )
+        .unwrap()
+        .into();
+
+        champ.ability_sets[0].extend(vec![201, 211]);
+        champ.ability_sets[1].extend(vec![301, 311]);
+        champ.defaults = [201, 311];
+
+        assert_eq!(champ.upgrades(&[]), Ok([Some(201), Some(311)]));
+        assert_eq!(champ.upgrades(&[301, 211]), Ok([Some(211), Some(301)]));
+        assert_eq!(champ.upgrades(&[211]), Ok([Some(211), Some(311)]));
+        assert_eq!(champ.upgrades(&[101]), Err(InvalidUpgrade::NotAnUpgrade(101)));
+        assert_eq!(champ.upgrades(&[201, 211]), Err(InvalidUpgrade::SameSet(201, 211)));
+
+        assert_eq!(champ.picks([None, Some(301)]), Ok([Some(201), Some(301)]));
+        assert_eq!(champ.picks([Some(301), None]), Err(InvalidUpgrade::WrongSet(301, 0)));
+
+        champ.ability_sets[1].clear();
+
+        assert_eq!(champ.upgrades(&[]), Ok([Some(201), None]));
+    }
+}
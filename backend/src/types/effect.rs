@@ -14,18 +14,17 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use arrayvec::ArrayString;
-use serde::Serialize;
-use serde_repr::Serialize_repr;
+use serde::{Serialize, Deserialize};
+use serde_repr::{Serialize_repr, Deserialize_repr};
 
 use crate::db::{AutoIndexItem, TableItem, Searchable, SearchId};
 use crate::types::{Id, Tags};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Effect {
     pub id: Id,
-    pub key: ArrayString<[u8; 15]>,
+    pub key: Box<str>,
     pub name: Box<str>,
     pub desc: Box<str>,
     pub kind: EffectKind,
@@ -33,12 +32,13 @@ pub struct Effect {
     pub tags: Tags,
 }
 
-#[derive(Debug, Serialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum EffectKind {
     None,
     Damage,
     Condition,
+    Mechanic,
 }
 
 impl TableItem for Effect {
@@ -48,7 +48,7 @@ impl TableItem for Effect {
 }
 
 impl AutoIndexItem for Effect {
-    type Key = ArrayString<[u8; 15]>;
+    type Key = Box<str>;
 
     fn create(id: Id, key: Self::Key) -> Self {
         Effect {
@@ -61,21 +61,27 @@ impl AutoIndexItem for Effect {
         }
     }
 
-    fn make_key(key: &str) -> Self::Key {
-        let mut key = ArrayString::from(key).expect("Key in Map is too large");
+    fn key(&self) -> &str {
+        &self.key
+    }
 
-        key.make_ascii_lowercase();
-        key
+    fn make_key(key: &str) -> Self::Key {
+        key.trim()
+            .chars()
+            .map(|ch| if ch.is_whitespace() { '_' } else { ch.to_ascii_lowercase() })
+            .collect::<String>()
+            .into()
     }
 }
 
 impl Searchable for Effect {
-    fn search_id(&self) -> SearchId {
+    fn search_id(&self) -> SearchId<'_> {
         let key = &self.key;
 
         match self.kind {
             EffectKind::Condition => SearchId::Condition(key),
             EffectKind::Damage => SearchId::Damage(key),
+            EffectKind::Mechanic => SearchId::Mechanic(key),
             EffectKind::None => SearchId::Effect(key),
         }
     }
@@ -14,13 +14,25 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::marker::PhantomData;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
 use serde_repr::Serialize_repr;
 
 use crate::types::Id;
 use crate::db::{TableItem, EnumId};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum RuneKind {
+    Champion,
+    Spell,
+    Equip,
+    Relic,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Size {
     #[serde(rename = "1x1")]
     OneByOne,
@@ -28,61 +40,113 @@ pub enum Size {
     TwoByTwo,
 }
 
-// TODO: Do a custom Deserialize that handles both strings and ints
-#[derive(Serialize_repr, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize_repr, Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum Rarity {
-    #[serde(rename = "COMMON")]
     Common,
-    #[serde(rename = "UNCOMMON")]
     Uncommon,
-    #[serde(rename = "RARE")]
     Rare,
-    #[serde(rename = "EXOTIC")]
     Exotic,
-    #[serde(rename = "LIMITED")]
     Limited,
-    #[serde(rename = "LEGENDARY")]
     Legendary,
 }
 
-// TODO: Do a custom Deserialize that handles both strings and ints
-#[derive(Serialize_repr, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize_repr, Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum Faction {
-    #[serde(rename = "Savage Tundra")]
     SavageTundra = 0,
-    #[serde(rename = "Ironfist Stronghold")]
     IronfistStronghold = 1,
-    #[serde(rename = "K'thir Forest")]
     KthirForest = 2,
-    #[serde(rename = "Forglar Swamp")]
     ForglarSwamp = 3,
-    #[serde(rename = "Shattered Peaks")]
     ShatteredPeaks = 4,
-    #[serde(rename = "Sundered Lands")]
     SunderedLands = 5,
-    #[serde(rename = "Underdepths")]
     Underdepths = 6,
-    #[serde(rename = "Forsaken Wastes")]
     ForsakenWastes = 7,
 }
 
+/// Enums that are named in the feed, but serialized by us as integers.
+/// Deserializing them has to handle both.
+trait NamedEnum: Sized + Copy + 'static {
+    const EXPECTING: &'static str;
+    /// All variants in order of their integer representation
+    const VARIANTS: &'static [(Self, &'static str)];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::VARIANTS.iter().find(|(_, n)| *n == name).map(|(v, _)| *v)
+    }
+
+    fn from_repr(repr: u64) -> Option<Self> {
+        Self::VARIANTS.get(repr as usize).map(|(v, _)| *v)
+    }
+}
+
+struct NamedEnumVisitor<T>(PhantomData<T>);
+
+impl<'de, T: NamedEnum> Visitor<'de> for NamedEnumVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(T::EXPECTING)
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<T, E> {
+        T::from_name(name).ok_or_else(|| E::invalid_value(de::Unexpected::Str(name), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, repr: u64) -> Result<T, E> {
+        T::from_repr(repr).ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(repr), &self))
+    }
+}
+
+impl NamedEnum for Rarity {
+    const EXPECTING: &'static str = "rarity name or id";
+    const VARIANTS: &'static [(Self, &'static str)] = &[
+        (Rarity::Common, "COMMON"),
+        (Rarity::Uncommon, "UNCOMMON"),
+        (Rarity::Rare, "RARE"),
+        (Rarity::Exotic, "EXOTIC"),
+        (Rarity::Limited, "LIMITED"),
+        (Rarity::Legendary, "LEGENDARY"),
+    ];
+}
+
+impl NamedEnum for Faction {
+    const EXPECTING: &'static str = "faction name or id";
+    const VARIANTS: &'static [(Self, &'static str)] = &[
+        (Faction::SavageTundra, "Savage Tundra"),
+        (Faction::IronfistStronghold, "Ironfist Stronghold"),
+        (Faction::KthirForest, "K'thir Forest"),
+        (Faction::ForglarSwamp, "Forglar Swamp"),
+        (Faction::ShatteredPeaks, "Shattered Peaks"),
+        (Faction::SunderedLands, "Sundered Lands"),
+        (Faction::Underdepths, "Underdepths"),
+        (Faction::ForsakenWastes, "Forsaken Wastes"),
+    ];
+}
+
+impl<'de> Deserialize<'de> for Rarity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NamedEnumVisitor(PhantomData))
+    }
+}
+
+impl<'de> Deserialize<'de> for Faction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NamedEnumVisitor(PhantomData))
+    }
+}
+
+impl Faction {
+    pub fn name(self) -> &'static str {
+        Self::VARIANTS[self as usize].1
+    }
+}
+
 impl EnumId for Faction {
     const SIZE: usize = 8;
 
     fn from_id(id: Id) -> Option<Faction> {
-        match id {
-            0 => Some(Faction::SavageTundra),
-            1 => Some(Faction::IronfistStronghold),
-            2 => Some(Faction::KthirForest),
-            3 => Some(Faction::ForglarSwamp),
-            4 => Some(Faction::ShatteredPeaks),
-            5 => Some(Faction::SunderedLands),
-            6 => Some(Faction::Underdepths),
-            7 => Some(Faction::ForsakenWastes),
-            _ => None
-        }
+        Self::from_repr(id as u64)
     }
 }
 
@@ -15,12 +15,13 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::marker::PhantomData;
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 
-use crate::types::{Id, Ability, EntityId, Tags};
-use crate::db::{AutoIndexItem, TableItem};
+use crate::types::{Id, Ability, EntityId, Rarity, Tags};
+use crate::db::{AutoIndexItem, Table, TableItem, SearchId};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct Group<T> {
     pub id: Id,
     pub name: Box<str>,
@@ -34,8 +35,17 @@ pub struct Shim<'a> {
     pub name: &'a str,
 }
 
+/// Like `Shim`, but for any searchable entity, carrying its kind and rarity.
+#[derive(Debug, Serialize)]
+pub struct EntityShim<'a> {
+    pub name: &'a str,
+    #[serde(flatten)]
+    pub sid: SearchId<'a>,
+    pub rarity: Option<Rarity>,
+}
+
 impl<T> Group<T> {
-    pub fn shim(&self) -> Shim {
+    pub fn shim(&self) -> Shim<'_> {
         Shim {
             id: self.id,
             name: &self.name,
@@ -63,6 +73,10 @@ impl<T> AutoIndexItem for Group<T> {
         }
     }
 
+    fn key(&self) -> &str {
+        &self.name
+    }
+
     fn make_key(key: &str) -> Self::Key {
         key.into()
     }
@@ -74,17 +88,21 @@ impl<T> TableItem for Group<T> {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AbilityGroup {
     pub id: Id,
     pub name: Box<str>,
+    /// Abilities in the group, ordered by level.
     pub ranks: Vec<Id>,
+    /// Pairs of abilities with the same level, but different descriptions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<[Id; 2]>,
     #[serde(skip)]
     pub tags: Tags,
 }
 
 impl AbilityGroup {
-    pub fn shim(&self) -> Shim {
+    pub fn shim(&self) -> Shim<'_> {
         Shim {
             id: self.id,
             name: &self.name,
@@ -101,6 +119,46 @@ impl AbilityGroup {
         self
     }
 
+    /// Order ranks by level, then id, numbering each distinct level starting at 1,
+    /// so that levels 1, 2 and 4 become ranks 1, 2 and 3. Abilities of the same level
+    /// share their rank, and are recorded in `conflicts` if their descriptions differ.
+    /// Returns the number of abilities that duplicate another one of the same level
+    /// with an identical description.
+    pub fn order_ranks(&mut self, abilities: &mut Table<Ability>) -> usize {
+        let core = |id| abilities.get(id).map(|ability| &ability.core);
+        let level = |id| core(id).map(|core| core.level);
+
+        // Ranks are already ordered by id, which the stable sort keeps within a level
+        self.ranks.sort_by_cached_key(|&id| level(id));
+        self.conflicts.clear();
+
+        let mut numbered = Vec::with_capacity(self.ranks.len());
+        let mut duplicates = 0;
+
+        for (rank, same_level) in self.ranks.chunk_by(|&a, &b| level(a) == level(b)).enumerate() {
+            let first = same_level[0];
+            let desc = |id| core(id).map(|core| &core.short_description);
+
+            for &id in &same_level[1..] {
+                if desc(id) != desc(first) {
+                    self.conflicts.push([first, id]);
+                } else {
+                    duplicates += 1;
+                }
+            }
+
+            numbered.extend(same_level.iter().map(|&id| (id, rank as u8 + 1)));
+        }
+
+        for (id, rank) in numbered {
+            if let Some(ability) = abilities.get_mut(id) {
+                ability.rank = rank;
+            }
+        }
+
+        duplicates
+    }
+
     pub fn tag(&mut self, taggable: impl Into<EntityId>) -> &mut Self {
         self.tags.tag(taggable);
         self
@@ -115,10 +173,15 @@ impl AutoIndexItem for AbilityGroup {
             id,
             name,
             ranks: Vec::new(),
+            conflicts: Vec::new(),
             tags: Tags::default(),
         }
     }
 
+    fn key(&self) -> &str {
+        &self.name
+    }
+
     fn make_key(key: &str) -> Self::Key {
         key.into()
     }
@@ -129,3 +192,38 @@ impl TableItem for AbilityGroup {
         self.id
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::AbilityCore;
+
+    #[test]
+    fn order_ranks() {
+        let mut abilities = Table::<Ability>::default();
+        let mut group = AbilityGroup::create(0, "Flanking".into());
+
+        for &(id, level, desc) in &[(40, 5, "Third"), (10, 1, "First"), (21, 2, "Second"), (20, 2, "Second"), (31, 5, "Other")] {
+            let ability = abilities.entry(AbilityCore {
+                id,
+                ap_cost: 0,
+                name: "Flanking".into(),
+                short_description: desc.into(),
+                activation_type: 0,
+                level,
+                cooldown: 0,
+                nora_cost: 0,
+                icon_name: "flanking".into(),
+            });
+
+            group.rank(ability);
+        }
+
+        assert_eq!(group.order_ranks(&mut abilities), 1);
+
+        let ranks = group.ranks.iter().map(|&id| (id, abilities.get(id).unwrap().rank)).collect::<Vec<_>>();
+
+        assert_eq!(ranks, [(10, 1), (20, 2), (21, 2), (31, 3), (40, 3)]);
+        assert_eq!(group.conflicts, [[31, 40]]);
+    }
+}
@@ -27,11 +27,11 @@ mod group;
 mod rune_set;
 mod tags;
 
-pub use enums::{Size, Rarity, Faction};
+pub use enums::{Size, Rarity, Faction, RuneKind};
 pub use effect::{Effect, EffectKind};
-pub use ability::{Ability, AbilityCore};
+pub use ability::{Ability, AbilityCore, IconSize};
 pub use champ::{Champion, ChampionCore};
-pub use group::{AbilityGroup, Group, Shim};
+pub use group::{AbilityGroup, Group, Shim, EntityShim};
 pub use rune_set::RuneSet;
 pub use tags::{Tags, EntityId};
 
@@ -52,7 +52,7 @@ pub struct RuneRaw {
     pub deck_limit: u8,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Rune<C> {
     #[serde(flatten)]
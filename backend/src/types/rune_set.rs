@@ -14,10 +14,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use serde::{Serialize, Deserialize};
+
 use crate::db::{TableItem, AutoIndexItem};
-use crate::types::{Id, EntityId, Shim, Tags};
+use crate::types::{Id, EntityId, Faction, Shim, Tags};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RuneSet<K = Box<str>> {
     pub id: Id,
     pub name: K,
@@ -25,7 +27,7 @@ pub struct RuneSet<K = Box<str>> {
 }
 
 impl RuneSet<Box<str>> {
-    pub fn shim(&self) -> Shim {
+    pub fn shim(&self) -> Shim<'_> {
         Shim {
             id: self.id,
             name: &self.name,
@@ -33,6 +35,15 @@ impl RuneSet<Box<str>> {
     }
 }
 
+impl RuneSet<Faction> {
+    pub fn shim(&self) -> Shim<'_> {
+        Shim {
+            id: self.id,
+            name: self.name.name(),
+        }
+    }
+}
+
 impl<K> RuneSet<K> {
     pub fn tag(&mut self, taggable: impl Into<EntityId>) -> &mut Self {
         self.tags.tag(taggable);
@@ -64,6 +75,10 @@ impl AutoIndexItem for RuneSet<Box<str>> {
         }
     }
 
+    fn key(&self) -> &str {
+        &self.name
+    }
+
     fn make_key(key: &str) -> Self::Key {
         key.into()
     }
@@ -15,10 +15,11 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::fmt;
+use serde::{Serialize, Deserialize};
 
 use crate::types::Id;
 
-#[derive(Debug, Hash, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Hash, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub enum EntityId {
     Champion(Id),
     Spell(Id),
@@ -28,6 +29,8 @@ pub enum EntityId {
     Effect(Id),
 }
 
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Tags {
     inner: Vec<EntityId>,
 }
@@ -38,14 +41,6 @@ impl fmt::Debug for Tags {
     }
 }
 
-impl Default for Tags {
-    fn default() -> Self {
-        Tags {
-            inner: Vec::new(),
-        }
-    }
-}
-
 impl Tags {
     pub fn tag(&mut self, taggable: impl Into<EntityId>) -> &mut Self {
         let tid = taggable.into();
@@ -56,4 +51,8 @@ impl Tags {
 
         self
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.inner.iter().copied()
+    }
 }
@@ -0,0 +1,124 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Small helpers shared between modules.
+
+use std::fmt::Write;
+use std::{fs, io};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lowercase hexadecimal encoding of `bytes`.
+pub fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+
+    for byte in bytes {
+        write!(&mut hex, "{:02x}", byte).unwrap();
+    }
+
+    hex
+}
+
+/// Write a file through `write`, replacing `path` only once the new contents are
+/// complete and synced to disk.
+///
+/// The contents go to a temporary `.part` file next to `path` first, so a failure
+/// or a crash never leaves a truncated file behind. Every call gets its own
+/// temporary file, so concurrent writes to the same path don't clobber each other
+/// and the last rename wins.
+pub fn write_atomic<F, E>(path: &Path, write: F) -> Result<(), E>
+where
+    F: FnOnce(&mut io::BufWriter<fs::File>) -> Result<(), E>,
+    E: From<io::Error>,
+{
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+    let tmp = path.with_extension(format!(
+        "{}-{}.part",
+        process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed),
+    ));
+
+    let result = fs::File::create(&tmp).map_err(E::from).and_then(|file| {
+        let mut file = io::BufWriter::new(file);
+
+        write(&mut file)?;
+
+        file.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Temporary directory for tests, removed with everything in it when dropped.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// Create a new directory holding a small file for each of the `files` names.
+        pub fn with_files(files: &[&str]) -> Self {
+            static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+            let dir = std::env::temp_dir().join(format!(
+                "poxbase-test-{}-{}",
+                process::id(),
+                DIRS.fetch_add(1, Ordering::Relaxed),
+            ));
+
+            fs::create_dir_all(&dir).unwrap();
+
+            for name in files {
+                fs::write(dir.join(name), b"data").unwrap();
+            }
+
+            TempDir(dir)
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+
+        /// Sorted names of all files in the directory.
+        pub fn files(&self) -> Vec<String> {
+            let mut files = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+}
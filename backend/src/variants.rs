@@ -0,0 +1,211 @@
+// PoxBase
+// Copyright (C) 2020  Maciej Hirsz
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Resized and re-encoded variants of rune art.
+//!
+//! The CDN only has rune art as a large JPG, so thumbnails in each of the configured
+//! widths, as well as lossless WebP encodings, are generated when assets are synced and cached
+//! in the `variants` directory next to the mirrored art. Variants that are requested
+//! before they have been generated are created on demand.
+//!
+//! AVIF is recognized in queries, but answered with `501 Not Implemented` for now:
+//! the encoder in `image` depends on `rav1e`, which needs newer versions of crates
+//! our dependency tree is pinned to.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use serde::Deserialize;
+
+use crate::util;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Jpg,
+    Webp,
+    /// Not supported yet, see module docs.
+    Avif,
+}
+
+impl Format {
+    /// Formats that variants are generated in.
+    const ALL: [Format; 2] = [Format::Jpg, Format::Webp];
+
+    pub fn is_supported(self) -> bool {
+        Self::ALL.contains(&self)
+    }
+
+    fn ext(self) -> &'static str {
+        match self {
+            Format::Jpg => "jpg",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+        }
+    }
+
+    fn image_format(self) -> ImageFormat {
+        match self {
+            Format::Jpg => ImageFormat::Jpeg,
+            Format::Webp => ImageFormat::WebP,
+            Format::Avif => ImageFormat::Avif,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Jpg => "image/jpeg",
+            Format::Webp => "image/webp",
+            Format::Avif => "image/avif",
+        }
+    }
+}
+
+/// Query on `/assets/runes/{hash}.jpg` selecting a variant, eg. `?w=128&format=webp`.
+#[derive(Deserialize, Debug, Default)]
+pub struct VariantQuery {
+    pub w: Option<u32>,
+    pub format: Option<Format>,
+}
+
+impl VariantQuery {
+    pub fn is_original(&self) -> bool {
+        self.w.is_none() && self.format.unwrap_or(Format::Jpg) == Format::Jpg
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Variants {
+    dir: PathBuf,
+    sizes: Vec<u32>,
+}
+
+impl Variants {
+    /// Variants of art mirrored into the assets directory `dir`, in the given widths.
+    pub fn new(dir: PathBuf, mut sizes: Vec<u32>) -> Self {
+        sizes.retain(|&width| width > 0);
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        Variants { dir, sizes }
+    }
+
+    fn source(&self, hash: &str) -> PathBuf {
+        self.dir.join("runes").join(format!("{}.jpg", hash))
+    }
+
+    fn path(&self, hash: &str, width: Option<u32>, format: Format) -> PathBuf {
+        let name = match width {
+            Some(width) => format!("{}-{}.{}", hash, width, format.ext()),
+            None => format!("{}.{}", hash, format.ext()),
+        };
+
+        self.dir.join("variants").join(name)
+    }
+
+    /// All variants that should exist for every rune, the original JPG excluded.
+    fn all(&self) -> impl Iterator<Item = (Option<u32>, Format)> + '_ {
+        std::iter::once(None)
+            .chain(self.sizes.iter().copied().map(Some))
+            .flat_map(|width| Format::ALL.iter().map(move |&format| (width, format)))
+            .filter(|&variant| variant != (None, Format::Jpg))
+    }
+
+    /// Generate all missing variants of the art for `hash`, returning how many were created.
+    pub fn generate(&self, hash: &str) -> anyhow::Result<usize> {
+        let missing = self
+            .all()
+            .filter(|&(width, format)| !self.path(hash, width, format).exists())
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() || !self.source(hash).exists() {
+            return Ok(0);
+        }
+
+        let image = image::open(self.source(hash))?;
+
+        for &(width, format) in &missing {
+            self.encode(&image, &self.path(hash, width, format), width, format)?;
+        }
+
+        Ok(missing.len())
+    }
+
+    /// Path to a single variant, generating it if it's not cached yet. Returns `None`
+    /// if the width isn't one of the configured sizes, or there is no art for `hash`.
+    pub fn get(&self, hash: &str, query: &VariantQuery) -> Option<PathBuf> {
+        let format = query.format.unwrap_or(Format::Jpg);
+
+        if !format.is_supported() {
+            return None;
+        }
+
+        if let Some(width) = query.w {
+            if !self.sizes.contains(&width) {
+                return None;
+            }
+        }
+
+        let path = self.path(hash, query.w, format);
+
+        if path.exists() {
+            return Some(path);
+        }
+
+        let source = self.source(hash);
+
+        if !source.exists() {
+            return None;
+        }
+
+        let result = image::open(source)
+            .context("Failed to decode art")
+            .and_then(|image| self.encode(&image, &path, query.w, format));
+
+        match result {
+            Ok(()) => Some(path),
+            Err(err) => {
+                log::error!("❌ Failed generating {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    fn encode(&self, image: &DynamicImage, path: &Path, width: Option<u32>, format: Format) -> anyhow::Result<()> {
+        let resized;
+        let image = match width {
+            // Never upscale, a variant wider than the art is the same as the original
+            Some(width) if width < image.width() => {
+                resized = image.resize(width, u32::MAX, FilterType::Lanczos3);
+                &resized
+            }
+            _ => image,
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Variants can be generated concurrently by the sync and by requests
+        util::write_atomic(path, |file| {
+            image.write_to(file, format.image_format())?;
+
+            Ok(())
+        })
+    }
+}
@@ -23,13 +23,19 @@ interface Props {
   ability: Ability;
 }
 
-export function AbilityIcon(props: Props) {
-  const { iconName } = props.ability;
+export function abilityIconUrl(ability: Ability): string {
+  const { iconName, iconSize } = ability;
+
+  return iconSize === 'small'
+    ? `/assets/small_icons/icon_${iconName}.gif`
+    : `/assets/big_icons/icon_${iconName}.png`;
+}
 
+export function AbilityIcon(props: Props) {
   return (
     <div
       className="AbilityIcon"
-      style={{ backgroundImage: `url('/assets/big_icons/icon_${iconName}.png')` }}
+      style={{ backgroundImage: `url('${abilityIconUrl(props.ability)}')` }}
     />
   );
 }
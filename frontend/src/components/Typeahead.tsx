@@ -46,7 +46,7 @@ interface ResultRune {
 interface ResultEffect {
   id: string,
   name: string,
-  kind: 'effect' | 'condition' | 'damage',
+  kind: 'effect' | 'condition' | 'mechanic' | 'damage',
   rarity: null,
 }
 
@@ -61,6 +61,7 @@ const KIND_TO_LABEL = {
   race: ['race','Race'],
   effect: ['effect', 'Effect'],
   condition: ['effect', 'Condition'],
+  mechanic: ['effect', 'Mechanic'],
   damage: ['effect', 'Damage Type'],
 };
 
@@ -18,6 +18,7 @@ import React from 'react';
 
 import { db, Id, Champion } from '../../db';
 import { AbstractRune } from './';
+import { abilityIconUrl } from '../AbilityIcon';
 
 interface Props {
   id: Id;
@@ -66,7 +67,7 @@ export class ChampionRune extends AbstractRune<Champion, Props> {
 
         return (
           <div key={id} className="Rune-ab">
-            <div className="Rune-ab-icon" style={{ backgroundImage: `url('/assets/big_icons/icon_${ability.iconName}.png')` }} />
+            <div className="Rune-ab-icon" style={{ backgroundImage: `url('${abilityIconUrl(ability)}')` }} />
             <img src={`/assets/frame/ability_${active}border.png`} className="Rune-ability-border" />
             <span>{name}</span>
           </div>
@@ -77,6 +77,7 @@ export interface Ability {
   id: Id,
   name: string,
   iconName: string,
+  iconSize: 'big' | 'small' | 'missing',
   activationType: number,
   shortDescription: string,
   level: number,
@@ -84,12 +85,14 @@ export interface Ability {
   apCost: number,
   cooldown: number,
   group: Id,
+  rank: number,
 }
 
 export interface AbilityGroup {
   id: Id,
   name: string,
   ranks: Id[],
+  conflicts?: [Id, Id][],
   champs: Id[],
 }
 